@@ -1,6 +1,10 @@
 
 //! Helper methods for working with arrays.
 
+use cube::FaceIterator;
+use std::cmp;
+use std::mem;
+
 /// Implemented by arrays of different lengths.
 pub trait Array<T> {
     /// Creates array from a function of each component index.
@@ -108,4 +112,433 @@ impl<T> Array<T> for [T, ..16] {
             f(12),f(13),f(14),f(15)
         ]
     }
-}
\ No newline at end of file
+}
+
+/// A 3D grid of voxels, stored in a flat vector.
+///
+/// The `x` axis is contiguous in memory, followed by `y` and then `z`.
+#[deriving(Clone, PartialEq, Show)]
+pub struct Chunk<T> {
+    dims: [u32, ..3],
+    data: Vec<T>
+}
+
+impl<T: Clone> Chunk<T> {
+    /// Creates a chunk of dimensions `[w, h, d]`, with every voxel set to a value.
+    pub fn filled(dims: [u32, ..3], value: T) -> Chunk<T> {
+        let [w, h, d] = dims;
+        Chunk {
+            dims: dims,
+            data: Vec::from_elem((w * h * d) as uint, value)
+        }
+    }
+
+    /// Sets every voxel from `min` (inclusive) to `max` (exclusive) to a value.
+    /// The region is clamped to the bounds of the chunk.
+    pub fn fill_region(&mut self, min: [u32, ..3], max: [u32, ..3], value: T) {
+        let [w, h, d] = self.dims;
+        let [x0, y0, z0] = min;
+        let (x1, y1, z1) = (cmp::min(max[0], w), cmp::min(max[1], h), cmp::min(max[2], d));
+        if x0 >= x1 || y0 >= y1 || z0 >= z1 {
+            return;
+        }
+
+        // Rows are contiguous when the region covers the whole `x` axis,
+        // and layers are contiguous when it also covers the whole `y` axis.
+        if x0 == 0 && x1 == w && y0 == 0 && y1 == h {
+            let (start, end) = (self.index(0, 0, z0), self.index(0, 0, z1));
+            self.fill_span(start, end, &value);
+        } else if x0 == 0 && x1 == w {
+            for z in range(z0, z1) {
+                let (start, end) = (self.index(0, y0, z), self.index(0, y1, z));
+                self.fill_span(start, end, &value);
+            }
+        } else {
+            for z in range(z0, z1) {
+                for y in range(y0, y1) {
+                    let (start, end) = (self.index(x0, y, z), self.index(x1, y, z));
+                    self.fill_span(start, end, &value);
+                }
+            }
+        }
+    }
+
+    // Sets a range of the backing vector to a value.
+    fn fill_span(&mut self, start: uint, end: uint, value: &T) {
+        for voxel in self.data.slice_mut(start, end).iter_mut() {
+            *voxel = value.clone();
+        }
+    }
+}
+
+impl<T: Copy + PartialEq> Chunk<T> {
+    /// Encodes the voxels as runs of `(value, length)`, in the order of `index`.
+    pub fn to_rle(&self) -> Vec<(T, u32)> {
+        let mut runs = vec![];
+        let mut voxels = self.data.iter();
+        let mut run = match voxels.next() {
+            Some(&first) => (first, 1u32),
+            None => return runs
+        };
+        for &voxel in voxels {
+            let (value, length) = run;
+            if value == voxel {
+                run = (value, length + 1);
+            } else {
+                runs.push(run);
+                run = (voxel, 1);
+            }
+        }
+        runs.push(run);
+        runs
+    }
+
+    /// Decodes a chunk from runs of `(value, length)`, in the order of `index`.
+    /// Returns an error if the lengths do not add up to the volume of the chunk.
+    pub fn from_rle(dims: [u32, ..3], runs: &[(T, u32)]) -> Result<Chunk<T>, String> {
+        let [w, h, d] = dims;
+        let volume = w as u64 * h as u64 * d as u64;
+        let total = runs.iter().fold(0u64, |sum, &(_, length)| sum + length as u64);
+        if total != volume {
+            return Err(format!("Expected runs of {} voxels in total, found {}", volume, total));
+        }
+
+        let mut data = Vec::with_capacity(volume as uint);
+        for &(value, length) in runs.iter() {
+            data.grow(length as uint, value);
+        }
+        Ok(Chunk {
+            dims: dims,
+            data: data
+        })
+    }
+}
+
+impl<T> Chunk<T> {
+    /// Returns the dimensions of the chunk.
+    pub fn dimensions(&self) -> [u32, ..3] {
+        self.dims
+    }
+
+    /// Returns `true` if the position is inside the chunk.
+    pub fn contains(&self, x: u32, y: u32, z: u32) -> bool {
+        let [w, h, d] = self.dims;
+        x < w && y < h && z < d
+    }
+
+    /// Computes the index of a voxel in the backing vector.
+    /// The `x` axis is contiguous, followed by `y` and then `z`.
+    /// Does not check bounds.
+    pub fn index(&self, x: u32, y: u32, z: u32) -> uint {
+        let [w, h, _] = self.dims;
+        ((z * h + y) * w + x) as uint
+    }
+
+    /// Gets a voxel, or `None` if out of bounds.
+    pub fn get(&self, x: u32, y: u32, z: u32) -> Option<&T> {
+        if self.contains(x, y, z) {
+            Some(&self.data[self.index(x, y, z)])
+        } else {
+            None
+        }
+    }
+
+    /// Gets a mutable voxel, or `None` if out of bounds.
+    pub fn get_mut(&mut self, x: u32, y: u32, z: u32) -> Option<&mut T> {
+        if self.contains(x, y, z) {
+            let i = self.index(x, y, z);
+            Some(&mut self.data[i])
+        } else {
+            None
+        }
+    }
+
+    /// Sets a voxel.
+    /// Returns `false` if out of bounds.
+    pub fn set(&mut self, x: u32, y: u32, z: u32, value: T) -> bool {
+        match self.get_mut(x, y, z) {
+            Some(voxel) => { *voxel = value; true }
+            None => false
+        }
+    }
+
+    /// Gets a voxel at a signed position, or `None` if out of bounds.
+    pub fn get_signed(&self, pos: [i32, ..3]) -> Option<&T> {
+        let [x, y, z] = pos;
+        if x < 0 || y < 0 || z < 0 {
+            None
+        } else {
+            self.get(x as u32, y as u32, z as u32)
+        }
+    }
+
+    /// Gets the neighbors of a voxel, in the same order as `cube::Face`.
+    /// Neighbors outside the chunk are `None`.
+    pub fn neighbors(&self, x: u32, y: u32, z: u32) -> [Option<&T>, ..6] {
+        let mut neighbors = [None, ..6];
+        for face in FaceIterator::new() {
+            let [dx, dy, dz] = face.direction();
+            let pos = [x as i32 + dx, y as i32 + dy, z as i32 + dz];
+            neighbors[face as uint] = self.get_signed(pos);
+        }
+        neighbors
+    }
+
+    /// Iterates through the voxels with their coordinates `(x, y, z, voxel)`,
+    /// in the order of `index`.
+    pub fn iter_coords(&self) -> Coords<T> {
+        Coords {
+            chunk: self,
+            index: 0
+        }
+    }
+
+    /// Returns the voxels in the order of `index`.
+    pub fn as_slice(&self) -> &[T] {
+        self.data.as_slice()
+    }
+}
+
+/// Iterates through the voxels of a chunk with their coordinates.
+pub struct Coords<'a, T: 'a> {
+    chunk: &'a Chunk<T>,
+    index: uint
+}
+
+impl<'a, T> Iterator<(u32, u32, u32, &'a T)> for Coords<'a, T> {
+    fn next(&mut self) -> Option<(u32, u32, u32, &'a T)> {
+        if self.index >= self.chunk.data.len() {
+            return None;
+        }
+
+        let [w, h, _] = self.chunk.dims;
+        let i = self.index as u32;
+        self.index += 1;
+        Some((i % w, (i / w) % h, i / (w * h), &self.chunk.data[i as uint]))
+    }
+}
+
+/// A 3D grid of voxels, storing each distinct value once in a palette.
+///
+/// Voxels are stored as indices into the palette, in the order of `Chunk::index`,
+/// packed with 4, 8 or 16 bits per voxel depending on the size of the palette.
+/// The index width grows when the palette no longer fits.
+/// Values are never removed from the palette.
+#[deriving(Clone, PartialEq, Show)]
+pub struct PalettedChunk<T> {
+    dims: [u32, ..3],
+    palette: Vec<T>,
+    // Bits per index, either 4, 8 or 16.
+    bits: uint,
+    // Packed indices, least significant bits first.
+    indices: Vec<u8>
+}
+
+impl<T: Clone + PartialEq> PalettedChunk<T> {
+    /// Creates a chunk of dimensions `[w, h, d]`, with every voxel set to a value.
+    pub fn filled(dims: [u32, ..3], value: T) -> PalettedChunk<T> {
+        let [w, h, d] = dims;
+        let volume = (w * h * d) as uint;
+        PalettedChunk {
+            dims: dims,
+            palette: vec![value],
+            bits: 4,
+            indices: Vec::from_elem((volume + 1) / 2, 0u8)
+        }
+    }
+
+    /// Creates a paletted copy of a chunk.
+    pub fn from_chunk(chunk: &Chunk<T>) -> PalettedChunk<T> {
+        let mut paletted = match chunk.as_slice().first() {
+            Some(first) => PalettedChunk::filled(chunk.dimensions(), first.clone()),
+            None => PalettedChunk {
+                dims: chunk.dimensions(),
+                palette: vec![],
+                bits: 4,
+                indices: vec![]
+            }
+        };
+        for (x, y, z, voxel) in chunk.iter_coords() {
+            paletted.set(x, y, z, voxel.clone());
+        }
+        paletted
+    }
+
+    /// Creates a plain chunk with the same voxels.
+    pub fn to_chunk(&self) -> Chunk<T> {
+        let volume = self.volume();
+        Chunk {
+            dims: self.dims,
+            data: range(0, volume).map(|i| self.palette[self.read(i)].clone()).collect()
+        }
+    }
+
+    /// Sets a voxel, adding the value to the palette if necessary.
+    /// Returns `false` if out of bounds.
+    /// Fails if the palette would exceed 65536 values.
+    pub fn set(&mut self, x: u32, y: u32, z: u32, value: T) -> bool {
+        if !self.contains(x, y, z) {
+            return false;
+        }
+
+        let entry = match self.palette.iter().position(|v| *v == value) {
+            Some(entry) => entry,
+            None => {
+                assert!(self.palette.len() < 1 << 16, "Palette exceeded 65536 values");
+                self.palette.push(value);
+                self.palette.len() - 1
+            }
+        };
+        while entry >= 1 << self.bits {
+            self.widen();
+        }
+        let i = self.index(x, y, z);
+        self.write(i, entry);
+        true
+    }
+
+    // Doubles the width of the indices, keeping the voxels.
+    fn widen(&mut self) {
+        let volume = self.volume();
+        let old = PalettedChunk {
+            dims: self.dims,
+            palette: vec![],
+            bits: self.bits,
+            indices: mem::replace(&mut self.indices, vec![])
+        };
+        self.bits *= 2;
+        self.indices = Vec::from_elem((volume * self.bits + 7) / 8, 0u8);
+        for i in range(0, volume) {
+            self.write(i, old.read(i));
+        }
+    }
+}
+
+impl<T> PalettedChunk<T> {
+    /// Returns the dimensions of the chunk.
+    pub fn dimensions(&self) -> [u32, ..3] {
+        self.dims
+    }
+
+    /// Returns `true` if the position is inside the chunk.
+    pub fn contains(&self, x: u32, y: u32, z: u32) -> bool {
+        let [w, h, d] = self.dims;
+        x < w && y < h && z < d
+    }
+
+    /// Computes the index of a voxel, in the same order as `Chunk::index`.
+    /// Does not check bounds.
+    pub fn index(&self, x: u32, y: u32, z: u32) -> uint {
+        let [w, h, _] = self.dims;
+        ((z * h + y) * w + x) as uint
+    }
+
+    /// Gets a voxel, or `None` if out of bounds.
+    pub fn get(&self, x: u32, y: u32, z: u32) -> Option<&T> {
+        if self.contains(x, y, z) {
+            Some(&self.palette[self.read(self.index(x, y, z))])
+        } else {
+            None
+        }
+    }
+
+    /// Returns the distinct values of the chunk.
+    pub fn palette(&self) -> &[T] {
+        self.palette.as_slice()
+    }
+
+    /// Returns the number of bits used to store each voxel, either 4, 8 or 16.
+    pub fn bits_per_voxel(&self) -> uint {
+        self.bits
+    }
+
+    // Number of voxels in the chunk.
+    fn volume(&self) -> uint {
+        let [w, h, d] = self.dims;
+        (w * h * d) as uint
+    }
+
+    // Reads the palette entry of a voxel.
+    fn read(&self, i: uint) -> uint {
+        match self.bits {
+            4 => ((self.indices[i / 2] >> ((i % 2) * 4)) & 0xF) as uint,
+            8 => self.indices[i] as uint,
+            _ => self.indices[i * 2] as uint | (self.indices[i * 2 + 1] as uint << 8)
+        }
+    }
+
+    // Writes the palette entry of a voxel.
+    fn write(&mut self, i: uint, entry: uint) {
+        match self.bits {
+            4 => {
+                let shift = (i % 2) * 4;
+                let byte = &mut self.indices[i / 2];
+                *byte = (*byte & !(0xF << shift)) | ((entry as u8) << shift);
+            }
+            8 => self.indices[i] = entry as u8,
+            _ => {
+                self.indices[i * 2] = entry as u8;
+                self.indices[i * 2 + 1] = (entry >> 8) as u8;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn out_of_bounds_is_none() {
+        let chunk = Chunk::filled([2, 3, 4], 0u8);
+        assert_eq!(chunk.get(1, 2, 3), Some(&0));
+        assert_eq!(chunk.get(2, 0, 0), None);
+        assert_eq!(chunk.get(0, 3, 0), None);
+        assert_eq!(chunk.get(0, 0, 4), None);
+        assert_eq!(chunk.get_signed([-1, 0, 0]), None);
+        assert_eq!(chunk.get_signed([0, -1, 0]), None);
+        assert_eq!(chunk.get_signed([0, 0, -1]), None);
+    }
+
+    #[test]
+    fn rle_round_trip() {
+        let mut mixed = Chunk::filled([4, 4, 4], 0u8);
+        mixed.fill_region([0, 0, 0], [4, 4, 2], 1);
+        mixed.set(3, 3, 3, 2);
+        let runs = mixed.to_rle();
+        assert_eq!(runs, vec![(1, 32), (0, 31), (2, 1)]);
+        assert_eq!(Chunk::from_rle([4, 4, 4], runs.as_slice()), Ok(mixed));
+
+        let uniform = Chunk::filled([4, 4, 4], 7u8);
+        let runs = uniform.to_rle();
+        assert_eq!(runs, vec![(7, 64)]);
+        assert_eq!(Chunk::from_rle([4, 4, 4], runs.as_slice()), Ok(uniform));
+    }
+
+    #[test]
+    fn fill_region_leaves_outside_untouched() {
+        let mut chunk = Chunk::filled([4, 4, 4], 0u8);
+        chunk.fill_region([1, 1, 1], [3, 3, 5], 1);
+        for (x, y, z, &voxel) in chunk.iter_coords() {
+            let inside = x >= 1 && x < 3 && y >= 1 && y < 3 && z >= 1;
+            assert_eq!(voxel, if inside { 1 } else { 0 });
+        }
+    }
+
+    #[test]
+    fn palette_grows_past_16_values() {
+        let mut chunk = PalettedChunk::filled([4, 4, 2], 0u32);
+        for i in range(0u32, 15) {
+            chunk.set(i % 4, i / 4, 0, i + 1);
+        }
+        assert_eq!(chunk.bits_per_voxel(), 4);
+        chunk.set(3, 3, 0, 16);
+        assert_eq!(chunk.bits_per_voxel(), 8);
+        assert_eq!(chunk.palette().len(), 17);
+
+        for i in range(0u32, 16) {
+            assert_eq!(chunk.get(i % 4, i / 4, 0), Some(&(i + 1)));
+            assert_eq!(chunk.get(i % 4, i / 4, 1), Some(&0));
+        }
+    }
+}
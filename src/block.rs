@@ -0,0 +1,328 @@
+//! Describe how blocks look.
+
+use std::collections::HashMap;
+
+use cube::{ Down, East, Face, FaceIterator, North, South, West };
+use texture::{ AlphaClass, AtlasBuilder, Cutout, Opaque, Translucent };
+
+/// Identifies the type of a block.
+pub type BlockId = u16;
+
+/// The block id of empty space.
+pub const AIR: BlockId = 0;
+
+/// The names of the tiles on each face of a block.
+#[deriving(Clone, PartialEq, Show)]
+pub struct BlockTextures {
+    // Tile names, in the same order as the faces.
+    faces: [String, ..6],
+    // Overlay tile names, in the same order as the faces.
+    overlays: [Option<String>, ..6],
+    // Alternative tile names, in the same order as the faces.
+    variants: [Vec<String>, ..6]
+}
+
+impl BlockTextures {
+    /// Uses the same tile on every face.
+    pub fn uniform(name: &str) -> BlockTextures {
+        BlockTextures::top_side_bottom(name, name, name)
+    }
+
+    /// Uses a tile on the top, one on the four sides and one on the bottom,
+    /// for example for grass.
+    pub fn top_side_bottom(top: &str, side: &str, bottom: &str) -> BlockTextures {
+        let side = side.to_string();
+        BlockTextures {
+            faces: [
+                bottom.to_string(),
+                top.to_string(),
+                side.clone(),
+                side.clone(),
+                side.clone(),
+                side
+            ],
+            overlays: [None, None, None, None, None, None],
+            variants: [vec![], vec![], vec![], vec![], vec![], vec![]]
+        }
+    }
+
+    /// Uses a tile on both the top and bottom, and one on the four sides,
+    /// for example for logs.
+    pub fn column(top_bottom: &str, side: &str) -> BlockTextures {
+        BlockTextures::top_side_bottom(top_bottom, side, top_bottom)
+    }
+
+    /// Gets the tile name of a face.
+    pub fn get(&self, face: Face) -> &str {
+        self.faces[face as uint].as_slice()
+    }
+
+    /// Sets the tile name of a face.
+    pub fn set(&mut self, face: Face, name: &str) {
+        self.faces[face as uint] = name.to_string();
+    }
+
+    /// Adds an alternative tile to a face, for example `stone_2` for `stone`.
+    /// Each block picks one of the tile and its variants from its position,
+    /// see `get_at`.
+    pub fn add_variant(&mut self, face: Face, name: &str) {
+        self.variants[face as uint].push(name.to_string());
+    }
+
+    /// Adds alternative tiles to every face, see `add_variant`.
+    pub fn with_variants(mut self, names: &[&str]) -> BlockTextures {
+        for face in FaceIterator::new() {
+            for name in names.iter() {
+                self.add_variant(face, *name);
+            }
+        }
+        self
+    }
+
+    /// Gets the tile name of a face followed by its variants.
+    pub fn variants(&self, face: Face) -> Vec<&str> {
+        let mut names = vec![self.get(face)];
+        names.extend(self.variants[face as uint].iter().map(|name| name.as_slice()));
+        names
+    }
+
+    /// Gets the tile name of a face for a block at a position in the world,
+    /// picked from the tile and its variants with `variant_index`.
+    /// A position always gets the same tile, so chunks can be meshed again without flicker.
+    pub fn get_at(&self, face: Face, pos: [i32, ..3]) -> &str {
+        let variants = &self.variants[face as uint];
+        match variant_index(pos, variants.len() + 1) {
+            0 => self.get(face),
+            i => variants[i - 1].as_slice()
+        }
+    }
+
+    /// Gets the overlay tile name of a face, if any.
+    pub fn overlay(&self, face: Face) -> Option<&str> {
+        self.overlays[face as uint].as_ref().map(|name| name.as_slice())
+    }
+
+    /// Sets an overlay tile on a face, drawn over the tile of the face
+    /// and tinted instead of it, for example for the sides of grass blocks.
+    /// The overlay should be transparent where the tile shows through.
+    /// Overlays are only drawn on cubes.
+    pub fn set_overlay(&mut self, face: Face, name: &str) {
+        self.overlays[face as uint] = Some(name.to_string());
+    }
+
+    /// Uses an overlay tile on the four sides, see `set_overlay`.
+    pub fn with_side_overlay(mut self, name: &str) -> BlockTextures {
+        for face in [North, South, West, East].iter() {
+            self.set_overlay(*face, name);
+        }
+        self
+    }
+}
+
+/// Picks one of `count` variants from a position in the world,
+/// such that the same position always picks the same variant,
+/// and neighbor positions look unrelated.
+/// Will fail if `count` is zero.
+pub fn variant_index(pos: [i32, ..3], count: uint) -> uint {
+    assert!(count > 0, "there must be at least one variant");
+    let [x, y, z] = pos;
+    let mut hash = (x as u32 * 73856093) ^ (y as u32 * 19349663) ^ (z as u32 * 83492791);
+    // Mix the bits, since the products differ little between neighbors.
+    hash = (hash ^ (hash >> 16)) * 0x45d9f3b;
+    hash = (hash ^ (hash >> 16)) * 0x45d9f3b;
+    hash = hash ^ (hash >> 16);
+    hash as uint % count
+}
+
+/// The geometry of a block.
+#[deriving(Clone, PartialEq, Eq, Show)]
+pub enum BlockShape {
+    /// A full cube.
+    Cube,
+    /// Two diagonal quads, for example for grass and flowers.
+    Cross,
+    /// The bottom half of a cube.
+    Slab
+}
+
+impl BlockShape {
+    /// Checks whether the shape fills a whole face of its voxel,
+    /// and so may hide the face of the neighbor on that side.
+    ///
+    /// A slab only fills its bottom face, and a cross fills none.
+    pub fn covers(&self, face: Face) -> bool {
+        match *self {
+            Cube => true,
+            Cross => false,
+            Slab => face == Down
+        }
+    }
+}
+
+/// What the mesher and light propagation need to know about a type of block.
+#[deriving(Clone, PartialEq, Show)]
+pub struct BlockInfo {
+    /// The tiles on each face.
+    pub textures: BlockTextures,
+    /// The geometry.
+    pub shape: BlockShape,
+    /// Whether the block is a cube that hides all the faces of its neighbors and blocks light.
+    /// Other shapes may still hide the neighbor faces they cover, see `BlockShape::covers`.
+    pub is_opaque: bool,
+    /// The transparency of the tile on each face, in the same order as the faces.
+    /// Faces with `Translucent` tiles are meshed separately,
+    /// see `mesh::build_registry_chunk_mesh`.
+    pub alpha_classes: [AlphaClass, ..6],
+    /// The light level emitted, from `0` to `light::MAX_LIGHT`.
+    pub emits_light: u8
+}
+
+/// The `BlockInfo` of every type of block.
+///
+/// Blocks without info are drawn like `AIR`.
+#[deriving(Clone)]
+pub struct BlockRegistry {
+    blocks: HashMap<BlockId, BlockInfo>
+}
+
+impl BlockRegistry {
+    /// Creates an empty registry.
+    pub fn new() -> BlockRegistry {
+        BlockRegistry { blocks: HashMap::new() }
+    }
+
+    /// Creates a builder which derives whether blocks are opaque from the tiles in an atlas.
+    pub fn builder<'a>(atlas: &'a mut AtlasBuilder) -> BlockRegistryBuilder<'a> {
+        BlockRegistryBuilder {
+            atlas: atlas,
+            registry: BlockRegistry::new()
+        }
+    }
+
+    /// Sets the info of a block, replacing any previous info.
+    pub fn insert(&mut self, id: BlockId, info: BlockInfo) {
+        self.blocks.insert(id, info);
+    }
+
+    /// Gets the info of a block.
+    pub fn get(&self, id: BlockId) -> Option<&BlockInfo> {
+        self.blocks.get(&id)
+    }
+
+    /// Returns `true` if a block hides the faces of its neighbors.
+    /// Blocks without info are not opaque.
+    pub fn is_opaque(&self, id: BlockId) -> bool {
+        self.get(id).map_or(false, |info| info.is_opaque)
+    }
+
+    /// Gets the light level emitted by a block, for example for `light::propagate`.
+    /// Blocks without info do not emit light.
+    pub fn emits_light(&self, id: BlockId) -> u8 {
+        self.get(id).map_or(0, |info| info.emits_light)
+    }
+}
+
+/// Fills a `BlockRegistry`, looking up the tiles of each block in an atlas.
+///
+/// ```ignore
+/// let registry = BlockRegistry::builder(&mut atlas)
+///     .block(1, BlockTextures::uniform("stone"), Cube)
+///     .block(2, BlockTextures::uniform("glass"), Cube)
+///     .block(3, BlockTextures::uniform("torch"), Cross)
+///     .emits_light(3, 14)
+///     .build();
+/// ```
+pub struct BlockRegistryBuilder<'a> {
+    // The atlas containing the tiles of the blocks.
+    atlas: &'a mut AtlasBuilder,
+    registry: BlockRegistry
+}
+
+impl<'a> BlockRegistryBuilder<'a> {
+    /// Adds a block which does not emit light,
+    /// classifying its tiles and their variants with `AtlasBuilder::alpha_class`.
+    /// The block is opaque if it is a cube and every tile is fully opaque.
+    /// Tiles missing from the atlas are classified as `Cutout`.
+    pub fn block(
+        mut self,
+        id: BlockId,
+        textures: BlockTextures,
+        shape: BlockShape
+    ) -> BlockRegistryBuilder<'a> {
+        let mut alpha_classes = [Cutout, ..6];
+        for face in FaceIterator::new() {
+            // A face is as transparent as its most transparent variant.
+            let mut class = Opaque;
+            for name in textures.variants(face).iter() {
+                match self.atlas.alpha_class(*name).unwrap_or(Cutout) {
+                    Translucent => class = Translucent,
+                    Cutout if class == Opaque => class = Cutout,
+                    _ => {}
+                }
+            }
+            alpha_classes[face as uint] = class;
+        }
+        let is_opaque = shape == Cube && alpha_classes.iter().all(|&class| class == Opaque);
+        self.registry.insert(id, BlockInfo {
+            textures: textures,
+            shape: shape,
+            is_opaque: is_opaque,
+            alpha_classes: alpha_classes,
+            emits_light: 0
+        });
+        self
+    }
+
+    /// Sets the light level emitted by a block.
+    /// Will fail if the block has not been added.
+    pub fn emits_light(mut self, id: BlockId, level: u8) -> BlockRegistryBuilder<'a> {
+        self.registry.blocks.get_mut(&id).expect("block has not been added").emits_light = level;
+        self
+    }
+
+    /// Creates the `BlockRegistry`.
+    pub fn build(self) -> BlockRegistry {
+        self.registry
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cube::{ Down, East, North, South, Up, West };
+    use super::*;
+
+    #[test]
+    fn top_side_bottom_uses_side_on_all_four_sides() {
+        let textures = BlockTextures::top_side_bottom("grass_top", "grass_side", "dirt");
+        assert_eq!(textures.get(Up), "grass_top");
+        assert_eq!(textures.get(Down), "dirt");
+        for &face in [North, South, West, East].iter() {
+            assert_eq!(textures.get(face), "grass_side");
+        }
+    }
+
+    #[test]
+    fn same_position_gets_same_variant() {
+        // Pinned, such that worlds keep their look when the hash changes by accident.
+        let expected = [([0, 0, 0], 0, "stone"), ([-100, 64, 7], 1, "stone_2"),
+            ([1, 0, 0], 3, "stone_2"), ([0, 1, 0], 0, "stone_3")];
+        let textures = BlockTextures::uniform("stone").with_variants(&["stone_2", "stone_3"]);
+        let rebuilt = BlockTextures::uniform("stone").with_variants(&["stone_2", "stone_3"]);
+        for &(pos, index, name) in expected.iter() {
+            assert_eq!(variant_index(pos, 4), index);
+            assert_eq!(textures.get_at(Up, pos), name);
+            assert_eq!(rebuilt.get_at(Up, pos), name);
+        }
+    }
+
+    #[test]
+    fn every_variant_appears() {
+        let mut seen = [false, ..4];
+        for x in range(-8i32, 8) {
+            for z in range(-8i32, 8) {
+                seen[variant_index([x, 0, z], 4)] = true;
+            }
+        }
+        assert_eq!(seen, [true, ..4]);
+    }
+}
@@ -0,0 +1,162 @@
+//! A first-person camera.
+
+use std::f32::consts::FRAC_PI_2;
+use vecmath::{ Matrix4, Vector3, vec3_add, vec3_cross, vec3_dot, vec3_len, vec3_scale };
+
+/// A first-person camera, looking along `-z` when yaw and pitch are zero.
+pub struct Camera {
+    /// Position of the camera.
+    pub position: Vector3<f32>,
+    /// Rotation around the `y` axis, in radians.
+    /// Positive values turn to the right.
+    pub yaw: f32,
+    /// Rotation up or down, in radians.
+    pub pitch: f32
+}
+
+impl Camera {
+    /// Creates a new camera at a position.
+    pub fn new(position: Vector3<f32>) -> Camera {
+        Camera {
+            position: position,
+            yaw: 0.0,
+            pitch: 0.0
+        }
+    }
+
+    /// Gets the direction the camera is looking at.
+    pub fn forward(&self) -> Vector3<f32> {
+        let (sy, cy) = (self.yaw.sin(), self.yaw.cos());
+        let (sp, cp) = (self.pitch.sin(), self.pitch.cos());
+        [sy * cp, sp, -cy * cp]
+    }
+
+    /// Gets the direction to the right of the camera.
+    pub fn right(&self) -> Vector3<f32> {
+        [self.yaw.cos(), 0.0, self.yaw.sin()]
+    }
+
+    /// Gets the direction above the camera.
+    pub fn up(&self) -> Vector3<f32> {
+        vec3_cross(self.right(), self.forward())
+    }
+
+    /// Moves the camera in the direction it is looking at.
+    pub fn move_forward(&mut self, distance: f32) {
+        self.position = vec3_add(self.position, vec3_scale(self.forward(), distance));
+    }
+
+    /// Moves the camera to the right.
+    pub fn move_right(&mut self, distance: f32) {
+        self.position = vec3_add(self.position, vec3_scale(self.right(), distance));
+    }
+
+    /// Moves the camera up, relative to its orientation.
+    pub fn move_up(&mut self, distance: f32) {
+        self.position = vec3_add(self.position, vec3_scale(self.up(), distance));
+    }
+
+    /// Turns the camera, in radians.
+    /// The pitch is clamped such that the camera never looks straight up or down.
+    pub fn look(&mut self, yaw: f32, pitch: f32) {
+        let limit = FRAC_PI_2 - 0.001;
+        self.yaw += yaw;
+        self.pitch = (self.pitch + pitch).max(-limit).min(limit);
+    }
+
+    /// Computes the column major view matrix.
+    pub fn view_matrix(&self) -> Matrix4<f32> {
+        let (f, s, u) = (self.forward(), self.right(), self.up());
+        let p = self.position;
+        [
+            [s[0], u[0], -f[0], 0.0],
+            [s[1], u[1], -f[1], 0.0],
+            [s[2], u[2], -f[2], 0.0],
+            [-vec3_dot(s, p), -vec3_dot(u, p), vec3_dot(f, p), 1.0]
+        ]
+    }
+}
+
+/// Computes a column major perspective projection matrix.
+/// The vertical field of view is in radians.
+pub fn perspective(fovy: f32, aspect: f32, near: f32, far: f32) -> Matrix4<f32> {
+    let f = 1.0 / (fovy / 2.0).tan();
+    [
+        [f / aspect, 0.0, 0.0, 0.0],
+        [0.0, f, 0.0, 0.0],
+        [0.0, 0.0, (far + near) / (near - far), -1.0],
+        [0.0, 0.0, 2.0 * far * near / (near - far), 0.0]
+    ]
+}
+
+/// A plane `[a, b, c, d]`, containing the points where `a * x + b * y + c * z + d = 0`.
+/// Points on the side of the normal `[a, b, c]` are in front of the plane.
+pub type Plane = [f32, ..4];
+
+/// Extracts the planes of the view frustum from a column major projection * view matrix,
+/// in the order left, right, bottom, top, near and far.
+/// The normals point into the frustum and are normalized.
+pub fn extract_frustum(view_proj: Matrix4<f32>) -> [Plane, ..6] {
+    let m = view_proj;
+    let row = |i: uint| [m[0][i], m[1][i], m[2][i], m[3][i]];
+    let (r0, r1, r2, r3) = (row(0), row(1), row(2), row(3));
+    let add = |a: [f32, ..4], b: [f32, ..4]| [a[0] + b[0], a[1] + b[1], a[2] + b[2], a[3] + b[3]];
+    let sub = |a: [f32, ..4], b: [f32, ..4]| [a[0] - b[0], a[1] - b[1], a[2] - b[2], a[3] - b[3]];
+
+    let mut planes = [add(r3, r0), sub(r3, r0), add(r3, r1), sub(r3, r1), add(r3, r2), sub(r3, r2)];
+    for plane in planes.iter_mut() {
+        let len = vec3_len([plane[0], plane[1], plane[2]]);
+        for x in plane.iter_mut() {
+            *x /= len;
+        }
+    }
+    planes
+}
+
+/// Returns `true` if an axis aligned box intersects the frustum,
+/// for example to skip drawing chunks outside of the view.
+/// Boxes near corners of the frustum can be reported as visible when they are not.
+pub fn aabb_in_frustum(planes: &[Plane, ..6], min: Vector3<f32>, max: Vector3<f32>) -> bool {
+    for plane in planes.iter() {
+        // The corner of the box furthest along the normal.
+        let mut p = [0.0f32, ..3];
+        for i in range(0u, 3) {
+            p[i] = if plane[i] >= 0.0 { max[i] } else { min[i] };
+        }
+        if vec3_dot([plane[0], plane[1], plane[2]], p) + plane[3] < 0.0 {
+            return false;
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frustum_of_identity_is_unit_cube() {
+        let identity = [
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0]
+        ];
+        assert_eq!(extract_frustum(identity).as_slice(), [
+            [1.0, 0.0, 0.0, 1.0],
+            [-1.0, 0.0, 0.0, 1.0],
+            [0.0, 1.0, 0.0, 1.0],
+            [0.0, -1.0, 0.0, 1.0],
+            [0.0, 0.0, 1.0, 1.0],
+            [0.0, 0.0, -1.0, 1.0]
+        ].as_slice());
+    }
+
+    #[test]
+    fn box_behind_camera_is_culled() {
+        // The identity view matrix looks along `-z` from the origin.
+        let planes = extract_frustum(perspective(1.0, 1.0, 0.1, 100.0));
+        assert!(!aabb_in_frustum(&planes, [-0.5, -0.5, 5.0], [0.5, 0.5, 6.0]));
+        assert!(aabb_in_frustum(&planes, [-0.5, -0.5, -6.0], [0.5, 0.5, -5.0]));
+    }
+}
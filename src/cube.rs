@@ -23,9 +23,13 @@
 //!      1  -------- 0
 //! ```
 
+use std::default::Default;
 use std::from_str::FromStr;
+use std::uint;
 
-use vecmath::Vector3;
+use glium::{ Display, IndexBuffer, VertexBuffer };
+use glium::index_buffer::{ LinesList, TrianglesList };
+use vecmath::{ Vector3, vec3_dot, vec3_len, vec3_sub };
 
 // Cube faces (clockwise).
 pub const QUADS: &'static [[uint, ..4], ..6] = &[
@@ -52,6 +56,61 @@ pub const VERTICES: &'static [Vector3<f32>, ..8] = &[
     [1.0, 1.0, 1.0]  // 7
 ];
 
+// Cube edges, as pairs of vertices.
+pub const EDGES: &'static [[uint, ..2], ..12] = &[
+    [0, 1], [1, 2], [2, 3], [3, 0], // north
+    [4, 5], [5, 6], [6, 7], [7, 4], // south
+    [0, 5], [1, 4], [2, 7], [3, 6]  // between north and south
+];
+
+// Texture coordinates of face corners, in the same order as `QUADS`.
+// Seen from outside, the corners of side faces are
+// bottom-right, bottom-left, top-left and top-right.
+pub const TEX_COORDS: &'static [[f32, ..2], ..4] = &[
+    [1.0, 0.0],
+    [0.0, 0.0],
+    [0.0, 1.0],
+    [1.0, 1.0]
+];
+
+// Triangles of a face, relative to its first corner.
+pub const QUAD_INDICES: &'static [u32, ..6] = &[0, 1, 2, 0, 2, 3];
+
+// Triangles of a face split along the other diagonal, see `flip_quad`.
+pub const FLIPPED_QUAD_INDICES: &'static [u32, ..6] = &[1, 2, 3, 1, 3, 0];
+
+/// A vertex of a generated mesh.
+#[deriving(Clone, PartialEq, Show)]
+pub struct Vertex {
+    /// Position of the vertex.
+    pub position: [f32, ..3],
+    /// Normal of the face.
+    pub normal: [f32, ..3],
+    /// Texture coordinates.
+    pub uv: [f32, ..2],
+    /// Texture of the face, for example a layer in a texture array.
+    pub texture: u32,
+    /// Ambient occlusion, from `0.0` (fully occluded) to `1.0` (not occluded).
+    pub ao: f32,
+    /// Color multiplied with the texture, for example a biome tint or baked light.
+    /// Opaque white leaves the texture unchanged.
+    pub color: [f32, ..4]
+}
+
+implement_vertex!(Vertex, position, normal, uv, texture, ao, color)
+
+/// The vertex color of untinted faces.
+pub const WHITE: [f32, ..4] = [1.0, 1.0, 1.0, 1.0];
+
+/// A vertex with only a position.
+#[deriving(Clone, PartialEq, Show)]
+pub struct PositionVertex {
+    /// Position of the vertex.
+    pub position: [f32, ..3]
+}
+
+implement_vertex!(PositionVertex, position)
+
 /// A value representing face direction.
 #[repr(uint)]
 #[deriving(PartialEq, Eq, FromPrimitive, Show)]
@@ -95,6 +154,91 @@ impl Face {
         }
     }
 
+    /// Gets the face pointing the other way.
+    pub fn opposite(self) -> Face {
+        match self {
+            Down => Up,
+            Up => Down,
+            North => South,
+            South => North,
+            West => East,
+            East => West
+        }
+    }
+
+    /// Gets the normal of face.
+    pub fn normal(self) -> [f32, ..3] {
+        let [x, y, z] = self.direction();
+        [x as f32, y as f32, z as f32]
+    }
+
+    /// Computes the ambient occlusion level of each corner of the face of a voxel,
+    /// in the same order as `vertices`.
+    /// Levels go from `0` (fully occluded) to `3` (not occluded).
+    ///
+    /// `is_solid` is called with the positions of the voxels around the corners,
+    /// in the layer in front of the face.
+    /// A corner is occluded by the two voxels along its sides and the voxel in its diagonal.
+    pub fn ambient_occlusion(self, pos: [i32, ..3], is_solid: |[i32, ..3]| -> bool) -> [u8, ..4] {
+        let mut levels = [0u8, ..4];
+        for (i, &[_, side1, side2, diagonal]) in self.corner_neighbors(pos).iter().enumerate() {
+            let (side1, side2) = (is_solid(side1), is_solid(side2));
+            levels[i] = if side1 && side2 {
+                0
+            } else {
+                3 - (side1 as u8 + side2 as u8 + is_solid(diagonal) as u8)
+            };
+        }
+        levels
+    }
+
+    /// Computes the positions of the voxels touching each corner of the face of a voxel,
+    /// in the layer in front of the face, in the same order as `vertices`.
+    /// Each corner has the voxel in front of the face, the two voxels along its sides,
+    /// and the voxel in its diagonal, in that order.
+    pub fn corner_neighbors(self, pos: [i32, ..3]) -> [[[i32, ..3], ..4], ..4] {
+        let [nx, ny, nz] = self.direction();
+        let front = [pos[0] + nx, pos[1] + ny, pos[2] + nz];
+        let axis = self.axis();
+        let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+
+        let mut corners = [[front, ..4], ..4];
+        for (i, &corner) in QUADS[self as uint].iter().enumerate() {
+            let c = VERTICES[corner];
+            let du = if c[u] > 0.5 { 1 } else { -1 };
+            let dv = if c[v] > 0.5 { 1 } else { -1 };
+
+            let mut side1 = front;
+            side1[u] += du;
+            let mut side2 = front;
+            side2[v] += dv;
+            let mut diagonal = side1;
+            diagonal[v] += dv;
+            corners[i] = [front, side1, side2, diagonal];
+        }
+        corners
+    }
+
+    /// Gets the normal, tangent and bitangent of face.
+    /// The tangent points along increasing `u` of `TEX_COORDS`,
+    /// and the bitangent along increasing `v`.
+    /// The basis is right-handed, such that `normal = tangent × bitangent`.
+    pub fn tangent_space(self) -> ([f32, ..3], [f32, ..3], [f32, ..3]) {
+        // The first corner is at `(1, 0)`, the second at `(0, 0)` and the third at `(0, 1)`.
+        let quad = QUADS[self as uint];
+        let (a, b, c) = (VERTICES[quad[0]], VERTICES[quad[1]], VERTICES[quad[2]]);
+        (self.normal(), vec3_sub(a, b), vec3_sub(c, b))
+    }
+
+    // Gets the axis of the face normal.
+    fn axis(self) -> uint {
+        match self {
+            West | East => 0,
+            Down | Up => 1,
+            North | South => 2
+        }
+    }
+
     /// Gets the face in a specific direction.
     pub fn from_direction(d: [i32, ..3]) -> Option<Face> {
         Some(match d {
@@ -147,4 +291,711 @@ impl Iterator<Face> for FaceIterator {
             _ => None
         }
     }
-}
\ No newline at end of file
+}
+
+/// Orientation of a block, as quarter turns around the `x` axis followed by the `y` axis.
+///
+/// A quarter turn around `x` turns `Up` into `South`,
+/// and a quarter turn around `y` turns `North` into `East`.
+#[deriving(Clone, PartialEq, Eq, Show)]
+pub struct BlockRotation {
+    /// Quarter turns around the `x` axis.
+    pub x: u8,
+    /// Quarter turns around the `y` axis.
+    pub y: u8
+}
+
+impl BlockRotation {
+    /// Creates a new rotation.
+    pub fn new(x: u8, y: u8) -> BlockRotation {
+        BlockRotation { x: x % 4, y: y % 4 }
+    }
+
+    /// Creates a rotation that keeps blocks unchanged.
+    pub fn identity() -> BlockRotation {
+        BlockRotation { x: 0, y: 0 }
+    }
+
+    /// Rotates a direction.
+    pub fn apply(&self, d: [i32, ..3]) -> [i32, ..3] {
+        let [mut x, mut y, mut z] = d;
+        for _ in range(0, self.x % 4) {
+            let (ny, nz) = (-z, y);
+            y = ny;
+            z = nz;
+        }
+        for _ in range(0, self.y % 4) {
+            let (nx, nz) = (-z, x);
+            x = nx;
+            z = nz;
+        }
+        [x, y, z]
+    }
+}
+
+/// Computes the face of a rotated block that a face of the unrotated block ends up on.
+pub fn rotate(face: Face, rotation: BlockRotation) -> Face {
+    Face::from_direction(rotation.apply(face.direction())).unwrap()
+}
+
+/// Computes how many quarter turns the texture of a face turns by
+/// when the block is rotated, counter-clockwise as seen from outside the block.
+/// The result is used with `rotate_tex_coords` on the rotated face.
+pub fn uv_rotation(face: Face, rotation: BlockRotation) -> u8 {
+    let to_i32 = |v: [f32, ..3]| [v[0] as i32, v[1] as i32, v[2] as i32];
+    let (_, tangent, _) = face.tangent_space();
+    let tangent = rotation.apply(to_i32(tangent));
+
+    let (_, t, b) = rotate(face, rotation).tangent_space();
+    let (t, b) = (to_i32(t), to_i32(b));
+    if tangent == t {
+        0
+    } else if tangent == b {
+        1
+    } else if tangent == [-t[0], -t[1], -t[2]] {
+        2
+    } else {
+        3
+    }
+}
+
+/// Computes the texture coordinates to sample at a point of a rotated face,
+/// given the quarter turns from `uv_rotation`.
+/// Texture coordinates are in the range `[0.0, 1.0]`, like `TEX_COORDS`.
+pub fn rotate_tex_coords(uv: [f32, ..2], quarter_turns: u8) -> [f32, ..2] {
+    let [mut s, mut t] = uv;
+    for _ in range(0, quarter_turns % 4) {
+        let (ns, nt) = (t, 1.0 - s);
+        s = ns;
+        t = nt;
+    }
+    [s, t]
+}
+
+/// Returns `true` if a face with these ambient occlusion levels should be split
+/// along the diagonal from its second to its fourth corner,
+/// using `FLIPPED_QUAD_INDICES` instead of `QUAD_INDICES`.
+/// This keeps the interpolated occlusion symmetric around the darkest corner.
+pub fn flip_quad(ao: [u8, ..4]) -> bool {
+    (ao[0] as u32 + ao[2] as u32) < (ao[1] as u32 + ao[3] as u32)
+}
+
+/// A set of faces of a cube.
+#[deriving(Clone, PartialEq, Eq, Show)]
+pub struct FaceSet {
+    bits: u8
+}
+
+impl FaceSet {
+    /// Creates an empty set.
+    pub fn empty() -> FaceSet {
+        FaceSet { bits: 0 }
+    }
+
+    /// Creates a set containing all faces.
+    pub fn all() -> FaceSet {
+        FaceSet { bits: 0b111111 }
+    }
+
+    /// Adds a face to the set.
+    pub fn insert(&mut self, face: Face) {
+        self.bits |= 1 << face as uint;
+    }
+
+    /// Removes a face from the set.
+    pub fn remove(&mut self, face: Face) {
+        self.bits &= !(1 << face as uint);
+    }
+
+    /// Returns `true` if the set contains a face.
+    pub fn contains(&self, face: Face) -> bool {
+        self.bits & (1 << face as uint) != 0
+    }
+
+    /// Returns `true` if the set contains no faces.
+    pub fn is_empty(&self) -> bool {
+        self.bits == 0
+    }
+
+    /// Iterates through the faces in the set.
+    pub fn iter(&self) -> FaceSetIterator {
+        FaceSetIterator {
+            set: self.clone(),
+            faces: FaceIterator::new()
+        }
+    }
+}
+
+/// Iterates through the faces in a `FaceSet`.
+pub struct FaceSetIterator {
+    set: FaceSet,
+    faces: FaceIterator
+}
+
+impl Iterator<Face> for FaceSetIterator {
+    fn next(&mut self) -> Option<Face> {
+        for face in self.faces.by_ref() {
+            if self.set.contains(face) {
+                return Some(face);
+            }
+        }
+        None
+    }
+}
+
+/// Computes the visible faces of a voxel, given which neighbors are solid.
+/// The neighbors are in the same order as the faces.
+pub fn visible_faces(solid_neighbors: [bool, ..6]) -> FaceSet {
+    let mut faces = FaceSet::empty();
+    for face in FaceIterator::new() {
+        if !solid_neighbors[face as uint] {
+            faces.insert(face);
+        }
+    }
+    faces
+}
+
+/// Computes the visible faces of the voxel at a position.
+/// `is_solid` is called with the position of each neighbor, which can be outside of the chunk,
+/// such that chunk borders can be handled by sampling the adjacent chunk.
+pub fn visible_faces_at(pos: [i32, ..3], is_solid: |[i32, ..3]| -> bool) -> FaceSet {
+    let [x, y, z] = pos;
+    let mut solid_neighbors = [false, ..6];
+    for face in FaceIterator::new() {
+        let [dx, dy, dz] = face.direction();
+        solid_neighbors[face as uint] = is_solid([x + dx, y + dy, z + dz]);
+    }
+    visible_faces(solid_neighbors)
+}
+
+/// Generates the faces of a cube with sides of `size`, with its lowest corner at `origin`.
+/// Texture coordinates span from `0.0` to `1.0` on every face, whatever the size.
+/// Will fail if the size is not positive.
+pub fn geometry(origin: Vector3<f32>, size: f32, faces: FaceSet) -> (Vec<Vertex>, Vec<u32>) {
+    assert!(size > 0.0, "cube size must be positive, found {}", size);
+
+    let mut vertices = vec![];
+    let mut indices = vec![];
+    for face in faces.iter() {
+        // This rectangle leaves `TEX_COORDS` unchanged.
+        let (quad, quad_indices) = face_vertices(face, size, origin, [0.0, 1.0, 1.0, 0.0]);
+        let start = vertices.len() as u32;
+        vertices.push_all(quad.as_slice());
+        for i in quad_indices.iter() {
+            indices.push(start + *i);
+        }
+    }
+    (vertices, indices)
+}
+
+/// Generates a single face of a cube with sides of `size`, with its lowest corner at `origin`,
+/// for example for a floor made only of top faces.
+/// The texture coordinates span the rectangle `[u0, v0, u1, v1]` of an atlas,
+/// with `v1` at the bottom of side faces, like the meshes of chunks.
+/// The indices are relative to the first vertex.
+pub fn face_vertices(
+    face: Face,
+    size: f32,
+    origin: Vector3<f32>,
+    uv_rect: [f32, ..4]
+) -> ([Vertex, ..4], [u32, ..6]) {
+    let [u0, v0, u1, v1] = uv_rect;
+    let corners = face.vertices(origin, [size, size, size]);
+    let mut vertices = [Vertex {
+        position: [0.0, 0.0, 0.0],
+        normal: face.normal(),
+        uv: [0.0, 0.0],
+        texture: 0,
+        ao: 1.0,
+        color: WHITE
+    }, ..4];
+    for (i, vertex) in vertices.iter_mut().enumerate() {
+        let [s, t] = TEX_COORDS[i];
+        vertex.position = corners[i];
+        vertex.uv = [u0 + (u1 - u0) * s, v1 + (v0 - v1) * t];
+    }
+    (vertices, *QUAD_INDICES)
+}
+
+/// Generates all faces of a unit cube with its lowest corner at the origin.
+pub fn unit_cube() -> (Vec<Vertex>, Vec<u32>) {
+    geometry([0.0, 0.0, 0.0], 1.0, FaceSet::all())
+}
+
+/// Generates the faces of a box within the unit cube at `origin`,
+/// going from `min` to `max` in the range `[0.0, 1.0]` on each axis,
+/// for example for slabs or other partial blocks.
+/// Texture coordinates are those of the full cube, clipped to the box,
+/// such that the texture is cut rather than squeezed.
+/// Will fail if the box is empty or not within the unit cube.
+pub fn box_geometry(
+    origin: Vector3<f32>,
+    min: Vector3<f32>,
+    max: Vector3<f32>,
+    faces: FaceSet
+) -> (Vec<Vertex>, Vec<u32>) {
+    for i in range(0u, 3) {
+        assert!(0.0 <= min[i] && min[i] < max[i] && max[i] <= 1.0,
+            "box must be non-empty and within the unit cube, found {} to {}", min, max);
+    }
+
+    let mut vertices = vec![];
+    let mut indices = vec![];
+    for face in faces.iter() {
+        let (normal, tangent, bitangent) = face.tangent_space();
+        // The corner at texture coordinates `(0, 0)`.
+        let corner = VERTICES[QUADS[face as uint][1]];
+
+        let start = vertices.len() as u32;
+        for &i in QUADS[face as uint].iter() {
+            let v = VERTICES[i];
+            let p = [
+                min[0] + (max[0] - min[0]) * v[0],
+                min[1] + (max[1] - min[1]) * v[1],
+                min[2] + (max[2] - min[2]) * v[2]
+            ];
+            let offset = vec3_sub(p, corner);
+            vertices.push(Vertex {
+                position: [origin[0] + p[0], origin[1] + p[1], origin[2] + p[2]],
+                normal: normal,
+                uv: [vec3_dot(offset, tangent), vec3_dot(offset, bitangent)],
+                texture: 0,
+                ao: 1.0,
+                color: WHITE
+            });
+        }
+        for i in QUAD_INDICES.iter() {
+            indices.push(start + *i);
+        }
+    }
+    (vertices, indices)
+}
+
+/// Generates the faces of a slab filling the bottom half of the unit cube at `origin`.
+pub fn slab_bottom(origin: Vector3<f32>, faces: FaceSet) -> (Vec<Vertex>, Vec<u32>) {
+    box_geometry(origin, [0.0, 0.0, 0.0], [1.0, 0.5, 1.0], faces)
+}
+
+/// Generates two diagonal quads crossing in the middle of a cube with sides of `size`,
+/// with its lowest corner at `origin`, for example for grass and flowers.
+/// Both quads use the rectangle `[u0, v0, u1, v1]` of an atlas.
+/// When `double_sided` is `true`, each quad is also emitted facing the other way,
+/// such that the plant is visible from both sides with back-face culling enabled.
+pub fn cross_mesh(
+    origin: Vector3<f32>,
+    size: f32,
+    uv_rect: [f32, ..4],
+    double_sided: bool
+) -> (Vec<Vertex>, Vec<u32>) {
+    // Corners of the quads, in the same order as `QUADS`, with their normals.
+    let h = 0.70710678;
+    let quads: [([[f32, ..3], ..4], [f32, ..3]), ..2] = [
+        ([[0.0, 0.0, 0.0], [1.0, 0.0, 1.0], [1.0, 1.0, 1.0], [0.0, 1.0, 0.0]], [h, 0.0, -h]),
+        ([[1.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, 1.0, 1.0], [1.0, 1.0, 0.0]], [h, 0.0, h])
+    ];
+
+    let [u0, v0, u1, v1] = uv_rect;
+    let mut vertices = vec![];
+    let mut indices = vec![];
+    for &(corners, normal) in quads.iter() {
+        let mut sides = vec![(corners, normal)];
+        if double_sided {
+            let back = [corners[1], corners[0], corners[3], corners[2]];
+            sides.push((back, [-normal[0], -normal[1], -normal[2]]));
+        }
+        for &(corners, normal) in sides.iter() {
+            let start = vertices.len() as u32;
+            for (corner, &[s, t]) in corners.iter().zip(TEX_COORDS.iter()) {
+                vertices.push(Vertex {
+                    position: [
+                        origin[0] + size * corner[0],
+                        origin[1] + size * corner[1],
+                        origin[2] + size * corner[2]
+                    ],
+                    normal: normal,
+                    uv: [u0 + (u1 - u0) * s, v1 + (v0 - v1) * t],
+                    texture: 0,
+                    ao: 1.0,
+                    color: WHITE
+                });
+            }
+            for i in QUAD_INDICES.iter() {
+                indices.push(start + *i);
+            }
+        }
+    }
+    (vertices, indices)
+}
+
+/// Computes the visible faces of a box within the unit cube, like `box_geometry`,
+/// given which neighbors are solid.
+/// Only faces on the border of the unit cube can be hidden by a neighbor,
+/// for example the bottom face of a bottom slab, but not its top face.
+pub fn box_visible_faces(
+    min: Vector3<f32>,
+    max: Vector3<f32>,
+    solid_neighbors: [bool, ..6]
+) -> FaceSet {
+    let mut faces = FaceSet::empty();
+    for face in FaceIterator::new() {
+        let axis = face.axis();
+        let on_border = if face.direction()[axis] < 0 {
+            min[axis] <= 0.0
+        } else {
+            max[axis] >= 1.0
+        };
+        if !(on_border && solid_neighbors[face as uint]) {
+            faces.insert(face);
+        }
+    }
+    faces
+}
+
+/// Creates buffers for drawing a cube with sides of `size`, with its lowest corner at `origin`.
+/// Only the faces in the set are included, such that hidden faces can be skipped.
+/// Will fail if the size is not positive.
+pub fn mesh(
+    display: &Display,
+    origin: Vector3<f32>,
+    size: f32,
+    faces: FaceSet
+) -> (VertexBuffer<Vertex>, IndexBuffer) {
+    let (vertices, indices) = geometry(origin, size, faces);
+    (VertexBuffer::new(display, vertices), IndexBuffer::new(display, TrianglesList(indices)))
+}
+
+/// Creates buffers for drawing the edges of a box as lines,
+/// for example to debug chunk borders.
+pub fn wireframe(
+    display: &Display,
+    min: Vector3<f32>,
+    max: Vector3<f32>
+) -> (VertexBuffer<PositionVertex>, IndexBuffer) {
+    let vertices = VERTICES.iter().map(|v| PositionVertex {
+        position: [
+            min[0] + (max[0] - min[0]) * v[0],
+            min[1] + (max[1] - min[1]) * v[1],
+            min[2] + (max[2] - min[2]) * v[2]
+        ]
+    }).collect();
+    let mut indices = vec![];
+    for &[a, b] in EDGES.iter() {
+        indices.push(a as u32);
+        indices.push(b as u32);
+    }
+    (VertexBuffer::new(display, vertices), IndexBuffer::new(display, LinesList(indices)))
+}
+
+/// An axis of a coordinate system.
+#[deriving(Clone, PartialEq, Eq, Show)]
+pub enum Axis {
+    /// The `x` axis.
+    XAxis,
+    /// The `y` axis.
+    YAxis,
+    /// The `z` axis.
+    ZAxis
+}
+
+/// The handedness of a coordinate system.
+#[deriving(Clone, PartialEq, Eq, Show)]
+pub enum Handedness {
+    /// The `z` axis is the cross product of `x` and `y`.
+    RightHanded,
+    /// The `z` axis is the cross product of `y` and `x`.
+    LeftHanded
+}
+
+/// The convention of a coordinate system, for converting the meshes of this module.
+///
+/// Every generator of this module uses `Y_UP_RIGHT_HANDED`.
+/// Other conventions, for example `z` up, are obtained with `convert`.
+#[deriving(Clone, PartialEq, Eq, Show)]
+pub struct CoordSystem {
+    /// The axis pointing up.
+    pub up_axis: Axis,
+    /// The handedness of the axes.
+    pub handedness: Handedness
+}
+
+/// The coordinate system of this module: `y` is up, and the axes are right-handed.
+pub const Y_UP_RIGHT_HANDED: CoordSystem = CoordSystem {
+    up_axis: YAxis,
+    handedness: RightHanded
+};
+
+impl Default for CoordSystem {
+    fn default() -> CoordSystem {
+        Y_UP_RIGHT_HANDED
+    }
+}
+
+impl CoordSystem {
+    /// Converts a position or direction from `Y_UP_RIGHT_HANDED` to this system.
+    /// The `up` direction goes to `up_axis` and `north` stays horizontal.
+    /// Left-handed systems also mirror the `z` axis, or the `y` axis when `z` is up.
+    pub fn convert_vector(&self, v: [f32, ..3]) -> [f32, ..3] {
+        let [x, y, z] = v;
+        match (self.up_axis, self.handedness) {
+            (XAxis, RightHanded) => [y, -x, z],
+            (XAxis, LeftHanded) => [y, -x, -z],
+            (YAxis, RightHanded) => [x, y, z],
+            (YAxis, LeftHanded) => [x, y, -z],
+            (ZAxis, RightHanded) => [x, -z, y],
+            (ZAxis, LeftHanded) => [x, z, y]
+        }
+    }
+
+    /// Converts a triangle mesh from `Y_UP_RIGHT_HANDED` to this system,
+    /// converting positions and normals with `convert_vector`.
+    /// The triangles of left-handed systems are reversed,
+    /// such that front faces keep their winding and cubes do not turn inside out.
+    pub fn convert(&self, mesh: (Vec<Vertex>, Vec<u32>)) -> (Vec<Vertex>, Vec<u32>) {
+        let (vertices, mut indices) = mesh;
+        let vertices = vertices.into_iter().map(|vertex| Vertex {
+            position: self.convert_vector(vertex.position),
+            normal: self.convert_vector(vertex.normal),
+            ..vertex
+        }).collect();
+        if self.handedness == LeftHanded {
+            for triangle in indices.as_mut_slice().chunks_mut(3) {
+                triangle.swap(1, 2);
+            }
+        }
+        (vertices, indices)
+    }
+}
+
+/// How `greedy_mesh_with` handles texture coordinates of merged faces.
+#[deriving(Clone, PartialEq, Eq, Show)]
+pub enum UvMode {
+    /// Merge faces into larger quads, with texture coordinates going from zero
+    /// to the size of the quad in voxels, which keeps the vertex count low.
+    /// The texture must repeat once per voxel, so it can not be a tile of an atlas:
+    /// coordinates past the tile would sample its neighbors instead of wrapping.
+    /// Use a layer of a texture array from `ArrayAtlasBuilder`,
+    /// with `Vertex::texture` as the layer and a repeating wrap mode.
+    Tiled,
+    /// Keep one quad per face, with the texture coordinates of the tile in an atlas,
+    /// at the cost of more vertices.
+    /// This works with a single atlas texture from `AtlasBuilder`.
+    Split
+}
+
+/// Generates a mesh for a chunk of voxels,
+/// merging adjacent faces with the same texture into larger quads.
+///
+/// `voxel` returns the texture of the solid voxel at a position,
+/// or `None` for empty space.
+/// Faces between two solid voxels are dropped,
+/// and voxels outside of the chunk are considered empty.
+/// The texture coordinates of a merged quad go from zero to its size in voxels,
+/// such that a repeating texture is drawn once per voxel, see `UvMode::Tiled`.
+pub fn greedy_mesh(
+    dims: [u32, ..3],
+    voxel: |u32, u32, u32| -> Option<u32>
+) -> (Vec<Vertex>, Vec<u32>) {
+    greedy_mesh_with(dims, Tiled, |_| [0.0, 0.0, 1.0, 1.0], voxel)
+}
+
+/// Generates a mesh for a chunk of voxels like `greedy_mesh`,
+/// choosing how to handle texture coordinates of merged faces.
+///
+/// With `Split`, `atlas_rect` returns the rectangle `[u0, v0, u1, v1]` of a texture
+/// in the atlas, for example from `AtlasBuilder::uv_rects`.
+/// It is not called with `Tiled`.
+pub fn greedy_mesh_with(
+    dims: [u32, ..3],
+    uv_mode: UvMode,
+    atlas_rect: |u32| -> [f32, ..4],
+    voxel: |u32, u32, u32| -> Option<u32>
+) -> (Vec<Vertex>, Vec<u32>) {
+    let [w, h, d] = dims;
+    let mut voxels = Vec::with_capacity((w * h * d) as uint);
+    for z in range(0, d) {
+        for y in range(0, h) {
+            for x in range(0, w) {
+                voxels.push(voxel(x, y, z));
+            }
+        }
+    }
+
+    // Looks up the texture of a voxel, which may be outside of the chunk.
+    fn at(voxels: &[Option<u32>], dims: [u32, ..3], p: [i32, ..3]) -> Option<u32> {
+        let [w, h, d] = dims;
+        let [x, y, z] = p;
+        if x < 0 || y < 0 || z < 0 || x >= w as i32 || y >= h as i32 || z >= d as i32 {
+            return None;
+        }
+        voxels[((z as u32 * h + y as u32) * w + x as u32) as uint]
+    }
+
+    // Longest run of faces merged into a quad along each axis.
+    let max_run = match uv_mode {
+        Tiled => uint::MAX,
+        Split => 1
+    };
+
+    let mut vertices = vec![];
+    let mut indices = vec![];
+    for face in FaceIterator::new() {
+        let n = face.direction();
+        // Axis of the normal and the two axes of the face plane.
+        let axis = face.axis();
+        let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+        let (du, dv) = (dims[u] as uint, dims[v] as uint);
+
+        let mut mask: Vec<Option<u32>> = Vec::from_elem(du * dv, None);
+        for slice in range(0, dims[axis] as i32) {
+            // Find the visible faces in this slice.
+            for j in range(0, dv) {
+                for i in range(0, du) {
+                    let mut p = [0i32, ..3];
+                    p[axis] = slice;
+                    p[u] = i as i32;
+                    p[v] = j as i32;
+                    let neighbor = [p[0] + n[0], p[1] + n[1], p[2] + n[2]];
+                    mask[j * du + i] = match at(voxels.as_slice(), dims, neighbor) {
+                        None => at(voxels.as_slice(), dims, p),
+                        Some(_) => None
+                    };
+                }
+            }
+
+            // Merge rectangles of faces with the same texture.
+            for j in range(0, dv) {
+                let mut i = 0;
+                while i < du {
+                    let texture = match mask[j * du + i] {
+                        Some(texture) => texture,
+                        None => { i += 1; continue; }
+                    };
+
+                    let mut width = 1;
+                    while width < max_run && i + width < du
+                        && mask[j * du + i + width] == Some(texture) {
+                        width += 1;
+                    }
+                    let mut height = 1;
+                    'grow: while height < max_run && j + height < dv {
+                        for k in range(0, width) {
+                            if mask[(j + height) * du + i + k] != Some(texture) {
+                                break 'grow;
+                            }
+                        }
+                        height += 1;
+                    }
+                    for l in range(0, height) {
+                        for k in range(0, width) {
+                            mask[(j + l) * du + i + k] = None;
+                        }
+                    }
+
+                    let mut base = [0.0f32, ..3];
+                    base[axis] = slice as f32;
+                    base[u] = i as f32;
+                    base[v] = j as f32;
+                    let mut scale = [1.0f32, ..3];
+                    scale[u] = width as f32;
+                    scale[v] = height as f32;
+                    let start = vertices.len();
+                    push_quad(&mut vertices, &mut indices, face, base, scale, 1.0, texture);
+                    if uv_mode == Split {
+                        // Map the corners into the tile, the atlas rows go downwards.
+                        let [u0, v0, u1, v1] = atlas_rect(texture);
+                        for vertex in vertices.slice_from_mut(start).iter_mut() {
+                            let [s, t] = vertex.uv;
+                            vertex.uv = [u0 + (u1 - u0) * s, v1 + (v0 - v1) * t];
+                        }
+                    }
+
+                    i += width;
+                }
+            }
+        }
+    }
+    (vertices, indices)
+}
+
+// Adds a face of a box to a mesh.
+// The texture coordinates grow by `uv_scale` per unit of length along the face.
+fn push_quad(
+    vertices: &mut Vec<Vertex>,
+    indices: &mut Vec<u32>,
+    face: Face,
+    base: Vector3<f32>,
+    scale: Vector3<f32>,
+    uv_scale: f32,
+    texture: u32
+) {
+    let corners = face.vertices(base, scale);
+    let width = vec3_len(vec3_sub(corners[0], corners[1])) * uv_scale;
+    let height = vec3_len(vec3_sub(corners[2], corners[1])) * uv_scale;
+
+    let start = vertices.len() as u32;
+    for (position, uv) in corners.iter().zip(TEX_COORDS.iter()) {
+        vertices.push(Vertex {
+            position: *position,
+            normal: face.normal(),
+            uv: [uv[0] * width, uv[1] * height],
+            texture: texture,
+            ao: 1.0,
+            color: WHITE
+        });
+    }
+    for i in QUAD_INDICES.iter() {
+        indices.push(start + *i);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn greedy_mesh_of_solid_chunk_is_six_quads() {
+        let (vertices, indices) = greedy_mesh([16, 16, 16], |_, _, _| Some(0));
+        assert_eq!(vertices.len(), 6 * 4);
+        assert_eq!(indices.len(), 6 * 6);
+    }
+
+    #[test]
+    fn split_mesh_uses_atlas_rects() {
+        let rects = [[0.0, 0.0, 0.5, 0.5], [0.5, 0.5, 1.0, 1.0]];
+        let (vertices, _) = greedy_mesh_with([2, 1, 1], Split, |texture| rects[texture as uint],
+            |x, _, _| Some(x));
+        // Ten faces are visible, none are merged.
+        assert_eq!(vertices.len(), 10 * 4);
+        for vertex in vertices.iter() {
+            let [u0, v0, u1, v1] = rects[vertex.texture as uint];
+            let [u, v] = vertex.uv;
+            assert!(u == u0 || u == u1);
+            assert!(v == v0 || v == v1);
+        }
+    }
+
+    #[test]
+    fn single_occluder_darkens_two_corners() {
+        // The occluder is east of the voxel above the face.
+        let levels = Up.ambient_occlusion([0, 0, 0], |pos| pos == [1, 1, 0]);
+        assert_eq!(levels, [2, 3, 3, 2]);
+        assert!(!flip_quad(levels));
+    }
+
+    #[test]
+    fn quarter_turn_rotates_north_to_east() {
+        assert_eq!(rotate(North, BlockRotation::new(0, 1)), East);
+        assert_eq!(rotate(Up, BlockRotation::new(0, 1)), Up);
+    }
+
+    #[test]
+    fn half_turn_mirrors_horizontal_axes() {
+        let rotation = BlockRotation::new(0, 2);
+        for face in FaceIterator::new() {
+            let [x, y, z] = face.direction();
+            assert_eq!(rotation.apply([x, y, z]), [-x, y, -z]);
+        }
+        assert_eq!(rotate(North, rotation), South);
+        assert_eq!(rotate(West, rotation), East);
+    }
+}
@@ -1,12 +1,22 @@
 #![deny(missing_docs)]
-#![feature(globs)]
+#![feature(globs, phase)]
 
 //! A voxel rendering library on top of Glium.
 
+#[phase(plugin, link)]
 extern crate glium;
 extern crate image;
+#[cfg(test)]
+extern crate test;
 extern crate vecmath;
 
 pub mod array;
+pub mod block;
+pub mod camera;
 pub mod cube;
+pub mod light;
+pub mod mesh;
+pub mod raycast;
+#[cfg(feature = "render")]
+pub mod render;
 pub mod texture;
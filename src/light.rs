@@ -0,0 +1,111 @@
+//! Propagate light through chunks.
+
+use std::cmp;
+use std::collections::RingBuf;
+
+use array::Chunk;
+use cube::FaceIterator;
+
+/// The highest light level.
+pub const MAX_LIGHT: u8 = 15;
+
+/// Light levels of the voxels of a chunk, from `0` to `MAX_LIGHT`.
+pub type LightGrid = Chunk<u8>;
+
+/// Propagates block light from emitters, such as torches, through a chunk.
+///
+/// `emitters` gives the light level emitted by a voxel,
+/// and `opacity` how much light is absorbed when entering it.
+/// Light decreases by at least one level per step,
+/// so a single emitter in empty space lights a diamond shape.
+pub fn propagate<T>(
+    chunk: &Chunk<T>,
+    emitters: |&T| -> u8,
+    opacity: |&T| -> u8
+) -> LightGrid {
+    let mut light = Chunk::filled(chunk.dimensions(), 0u8);
+    let mut queue = RingBuf::new();
+    for (x, y, z, voxel) in chunk.iter_coords() {
+        let level = cmp::min(emitters(voxel), MAX_LIGHT);
+        if level > 0 {
+            light.set(x, y, z, level);
+            queue.push_back([x, y, z]);
+        }
+    }
+    spread(chunk, &mut light, queue, opacity);
+    light
+}
+
+/// Propagates skylight through a chunk, assuming full light above it.
+///
+/// Light goes straight down each column without losing levels,
+/// except for what is absorbed by voxels with a non-zero `opacity`,
+/// and then spreads sideways like block light.
+pub fn skylight<T>(chunk: &Chunk<T>, opacity: |&T| -> u8) -> LightGrid {
+    let [w, h, d] = chunk.dimensions();
+    let mut light = Chunk::filled(chunk.dimensions(), 0u8);
+    let mut queue = RingBuf::new();
+    for z in range(0, d) {
+        for x in range(0, w) {
+            let mut level = MAX_LIGHT;
+            for y in range(0, h).rev() {
+                level = level.saturating_sub(opacity(chunk.get(x, y, z).unwrap()));
+                if level == 0 {
+                    break;
+                }
+                light.set(x, y, z, level);
+                queue.push_back([x, y, z]);
+            }
+        }
+    }
+    spread(chunk, &mut light, queue, opacity);
+    light
+}
+
+// Spreads light from queued voxels to their neighbors, breadth first.
+fn spread<T>(
+    chunk: &Chunk<T>,
+    light: &mut LightGrid,
+    mut queue: RingBuf<[u32, ..3]>,
+    opacity: |&T| -> u8
+) {
+    loop {
+        let [x, y, z] = match queue.pop_front() {
+            Some(pos) => pos,
+            None => break
+        };
+        let level = *light.get(x, y, z).unwrap();
+        for face in FaceIterator::new() {
+            let [dx, dy, dz] = face.direction();
+            let pos = [x as i32 + dx, y as i32 + dy, z as i32 + dz];
+            let voxel = match chunk.get_signed(pos) {
+                Some(voxel) => voxel,
+                None => continue
+            };
+            let [nx, ny, nz] = [pos[0] as u32, pos[1] as u32, pos[2] as u32];
+            let next = level.saturating_sub(cmp::max(opacity(voxel), 1));
+            if next > *light.get(nx, ny, nz).unwrap() {
+                light.set(nx, ny, nz, next);
+                queue.push_back([nx, ny, nz]);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use array::Chunk;
+    use super::*;
+
+    #[test]
+    fn torch_lights_a_diamond() {
+        let mut chunk = Chunk::filled([7, 7, 7], false);
+        chunk.set(3, 3, 3, true);
+        let light = propagate(&chunk, |&torch| if torch { MAX_LIGHT } else { 0 }, |_| 0);
+
+        let dist = |a: u32| if a > 3 { a - 3 } else { 3 - a };
+        for (x, y, z, &level) in light.iter_coords() {
+            assert_eq!(level as u32, MAX_LIGHT as u32 - dist(x) - dist(y) - dist(z));
+        }
+    }
+}
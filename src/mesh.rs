@@ -0,0 +1,959 @@
+//! Build meshes of chunks.
+
+use std::collections::HashMap;
+use std::collections::hash_map::{ Occupied, Vacant };
+use std::default::Default;
+use std::io::{ IoResult, Writer };
+use std::num::Float;
+
+use array::Chunk;
+use block::{ AIR, BlockId, BlockRegistry, BlockShape, BlockTextures, Cross, Cube, Slab };
+use cube::{ BlockRotation, FLIPPED_QUAD_INDICES, Face, FaceIterator, FaceSet, North,
+    PositionVertex, QUAD_INDICES, TEX_COORDS, Vertex, box_geometry, box_visible_faces, cross_mesh,
+    flip_quad, rotate, rotate_tex_coords, uv_rotation };
+use light::{ LightGrid, MAX_LIGHT };
+use texture::{ Opaque, Translucent };
+use vecmath::{ vec3_len, vec3_scale, vec3_sub };
+
+/// Builds a mesh of a chunk, with texture coordinates from an atlas.
+///
+/// Every block other than `AIR` is a solid cube,
+/// and faces between two solid blocks are dropped.
+/// `block_textures` gives the tiles of a block,
+/// which are looked up in `atlas_uvs`, for example from `AtlasBuilder::uv_rects`.
+/// Faces using a tile missing from the atlas get zero texture coordinates.
+/// Every face goes to `ChunkMesh::opaque`, and the translucent mesh stays empty.
+pub fn build_chunk_mesh<'a>(
+    chunk: &Chunk<BlockId>,
+    atlas_uvs: &HashMap<String, [f32, ..4]>,
+    block_textures: |BlockId| -> &'a BlockTextures
+) -> ChunkMesh {
+    build_rotated_chunk_mesh(chunk, atlas_uvs, block_textures, |_, _, _| BlockRotation::identity())
+}
+
+/// Builds a mesh of a chunk like `build_chunk_mesh`,
+/// rotating each block by the rotation at its position,
+/// for example for logs placed sideways.
+/// The tiles of a block move to the rotated faces, and turn with them.
+pub fn build_rotated_chunk_mesh<'a>(
+    chunk: &Chunk<BlockId>,
+    atlas_uvs: &HashMap<String, [f32, ..4]>,
+    block_textures: |BlockId| -> &'a BlockTextures,
+    rotations: |u32, u32, u32| -> BlockRotation
+) -> ChunkMesh {
+    let options = MeshOptions { rotations: Some(rotations), ..Default::default() };
+    build_lit_chunk_mesh(chunk, atlas_uvs, block_textures, options)
+}
+
+/// Builds a coarse mesh of a chunk, for example for distant chunks,
+/// by meshing the chunk downsampled with `downsample_chunk`.
+///
+/// Each cell of `factor` voxels per side is drawn as a single block,
+/// with one tile stretched over each face of the cell,
+/// so there are about `factor * factor` times fewer faces.
+/// The mesh covers the same space as the full mesh,
+/// and cells at the far border of a chunk whose size is not a multiple of `factor`
+/// stick out of it.
+/// Meshes of neighbor chunks at different levels of detail do not line up,
+/// which can leave holes along the seams, unless skirts are added.
+pub fn build_lod_chunk_mesh<'a>(
+    chunk: &Chunk<BlockId>,
+    factor: u32,
+    atlas_uvs: &HashMap<String, [f32, ..4]>,
+    block_textures: |BlockId| -> &'a BlockTextures
+) -> ChunkMesh {
+    let coarse = downsample_chunk(chunk, factor);
+    let mesh = build_chunk_mesh(&coarse, atlas_uvs, block_textures);
+    let scale = factor as f32;
+    let (vertices, indices) = mesh.opaque;
+    let vertices = vertices.into_iter().map(|vertex| {
+        Vertex { position: vec3_scale(vertex.position, scale), ..vertex }
+    }).collect();
+    ChunkMesh {
+        opaque: (vertices, indices),
+        translucent: mesh.translucent,
+        bounds: mesh.bounds.map(|bounds| MeshBounds {
+            min: vec3_scale(bounds.min, scale),
+            max: vec3_scale(bounds.max, scale),
+            center: vec3_scale(bounds.center, scale),
+            radius: bounds.radius * scale
+        })
+    }
+}
+
+/// Downsamples a chunk by `factor` along each axis,
+/// replacing each cell of `factor` voxels per side with its most common block.
+/// Ties go to blocks other than `AIR`, then to the lowest block id,
+/// such that a half filled cell stays solid.
+/// Cells at the far border are partial when the size is not a multiple of `factor`.
+/// Will fail if `factor` is zero.
+pub fn downsample_chunk(chunk: &Chunk<BlockId>, factor: u32) -> Chunk<BlockId> {
+    assert!(factor > 0, "downsampling factor must not be zero");
+    let [w, h, d] = chunk.dimensions();
+    let dims = [(w + factor - 1) / factor, (h + factor - 1) / factor, (d + factor - 1) / factor];
+    let mut coarse = Chunk::filled(dims, AIR);
+    let mut counts: Vec<HashMap<BlockId, uint>> = coarse.as_slice().iter()
+        .map(|_| HashMap::new())
+        .collect();
+    for (x, y, z, &block) in chunk.iter_coords() {
+        let cell = coarse.index(x / factor, y / factor, z / factor);
+        match counts[cell].entry(block) {
+            Occupied(entry) => { *entry.into_mut() += 1; }
+            Vacant(entry) => { entry.set(1); }
+        }
+    }
+
+    for z in range(0, dims[2]) {
+        for y in range(0, dims[1]) {
+            for x in range(0, dims[0]) {
+                let (mut best, mut best_count) = (AIR, 0);
+                for (&block, &count) in counts[coarse.index(x, y, z)].iter() {
+                    let wins_tie = block != AIR && (best == AIR || block < best);
+                    if count > best_count || (count == best_count && wins_tie) {
+                        best = block;
+                        best_count = count;
+                    }
+                }
+                coarse.set(x, y, z, best);
+            }
+        }
+    }
+    coarse
+}
+
+/// Builds a mesh of a chunk like `build_chunk_mesh`, with vertex colors,
+/// configured by `options`, see `MeshOptions`.
+///
+/// Each face is tinted by `MeshOptions::tints`,
+/// and darkened by the light level of the voxel in front of it.
+/// Faces of cubes with an overlay tile, see `BlockTextures::set_overlay`,
+/// get a second quad with the overlay, raised slightly to avoid z-fighting.
+/// The tint then only applies to the overlay, which reproduces the sides of grass blocks
+/// with alpha testing, and without a dedicated shader.
+///
+/// The corners of the faces of cubes get the ambient occlusion of the voxels covering them,
+/// see `cube::Face::ambient_occlusion`, and each face is split along the diagonal
+/// chosen by `cube::flip_quad`.
+///
+/// `MeshOptions::shapes` gives the geometry of a block, which is a cube by default.
+/// A face is hidden by the neighbor in front of it if the neighbor shape covers it,
+/// see `BlockShape::covers`.
+/// Crosses use the north tile of a block, and are lit by the voxel they are in.
+/// Rotations only apply to cubes.
+pub fn build_lit_chunk_mesh<'a, 'b>(
+    chunk: &Chunk<BlockId>,
+    atlas_uvs: &HashMap<String, [f32, ..4]>,
+    block_textures: |BlockId| -> &'a BlockTextures,
+    mut options: MeshOptions<'b>
+) -> ChunkMesh {
+    let mut shapes = options.shapes.take();
+    build_mesh(chunk, atlas_uvs, options, |block| {
+        let shape = match shapes {
+            Some(ref mut shapes) => (*shapes)(block),
+            None => Cube
+        };
+        let mut covers = [false, ..6];
+        for face in FaceIterator::new() {
+            covers[face as uint] = shape.covers(face);
+        }
+        Some(MeshedBlock {
+            textures: block_textures(block),
+            shape: shape,
+            covers: covers,
+            translucent: [false, ..6]
+        })
+    })
+}
+
+/// Builds a mesh of a chunk like `build_lit_chunk_mesh`,
+/// taking the tiles and shape of each block from a registry.
+///
+/// Only opaque tiles hide the faces of their neighbors,
+/// so faces behind glass or leaves are kept.
+/// Like with `build_lit_chunk_mesh`, the neighbor shape must also cover the face,
+/// so the side of a cube next to a slab is kept, but not its top below a slab.
+/// Faces with a `Translucent` tile go to a separate mesh, see `ChunkMesh`.
+/// Such faces are dropped between two cubes of the same block,
+/// so that water or glass shows only its outer surface.
+/// Blocks missing from the registry are skipped.
+/// `MeshOptions::shapes` is not used, since the registry gives the shapes.
+pub fn build_registry_chunk_mesh<'a, 'b>(
+    chunk: &Chunk<BlockId>,
+    atlas_uvs: &HashMap<String, [f32, ..4]>,
+    registry: &'a BlockRegistry,
+    options: MeshOptions<'b>
+) -> ChunkMesh {
+    build_mesh(chunk, atlas_uvs, options, |block| {
+        registry.get(block).map(|info| {
+            let (mut covers, mut translucent) = ([false, ..6], [false, ..6]);
+            for face in FaceIterator::new() {
+                let class = info.alpha_classes[face as uint];
+                let covered = info.shape.covers(face) && class == Opaque;
+                covers[face as uint] = info.is_opaque || covered;
+                translucent[face as uint] = class == Translucent;
+            }
+            MeshedBlock {
+                textures: &info.textures,
+                shape: info.shape,
+                covers: covers,
+                translucent: translucent
+            }
+        })
+    })
+}
+
+/// Options of `build_lit_chunk_mesh` and `build_registry_chunk_mesh`.
+///
+/// Every option has a default, so that only the options in use are set:
+///
+/// ```ignore
+/// let options = MeshOptions {
+///     light: Some(&light),
+///     smooth_lighting: true,
+///     ..Default::default()
+/// };
+/// ```
+pub struct MeshOptions<'a> {
+    /// The chunks adjacent to the chunk.
+    /// Faces at the border of the chunk are dropped when the block behind them
+    /// covers them in the adjacent chunk.
+    /// Defaults to `ChunkNeighbors::none()`.
+    pub neighbors: ChunkNeighbors<'a>,
+    /// The light levels of the voxels of the chunk, for example from `light::propagate`.
+    /// Light outside of the chunk is full, as is every face without a light grid.
+    /// Defaults to `None`.
+    pub light: Option<&'a LightGrid>,
+    /// Gives the rotation of the block at a position.
+    /// Defaults to `None`, which leaves every block unrotated.
+    pub rotations: Option<|u32, u32, u32|: 'a -> BlockRotation>,
+    /// Gives the RGB color of a face of the block at a position,
+    /// for example from `ColorMap::biome_color` for grass.
+    /// Defaults to `None`, which leaves every face white.
+    pub tints: Option<|[u32, ..3], Face|: 'a -> [f32, ..3]>,
+    /// Gives the geometry of a block, only for `build_lit_chunk_mesh`.
+    /// Defaults to `None`, which makes every block a cube.
+    pub shapes: Option<|BlockId|: 'a -> BlockShape>,
+    /// Lights each corner of the faces of cubes by averaging the light
+    /// of the voxels touching it in front of the face, see `cube::Face::corner_neighbors`,
+    /// instead of lighting the whole face by the voxel in front of it.
+    /// Opaque voxels are left out of the average.
+    /// Disabled by default.
+    pub smooth_lighting: bool,
+    /// The position of the chunk in the world, in voxels.
+    /// Blocks with variant tiles pick one from their position in the world,
+    /// see `BlockTextures::get_at`, so that the choice does not repeat in every chunk.
+    /// Defaults to the origin.
+    pub chunk_origin: [i32, ..3]
+}
+
+impl<'a> Default for MeshOptions<'a> {
+    fn default() -> MeshOptions<'a> {
+        MeshOptions {
+            neighbors: ChunkNeighbors::none(),
+            light: None,
+            rotations: None,
+            tints: None,
+            shapes: None,
+            smooth_lighting: false,
+            chunk_origin: [0, 0, 0]
+        }
+    }
+}
+
+/// The mesh of a chunk, split by how it must be drawn.
+pub struct ChunkMesh {
+    /// Faces with opaque or cutout tiles, drawn first with depth testing,
+    /// and alpha testing for cutout tiles.
+    pub opaque: (Vec<Vertex>, Vec<u32>),
+    /// Faces with translucent tiles, such as water or stained glass,
+    /// drawn after opaque faces with blending and without writing depth.
+    pub translucent: (Vec<Vertex>, Vec<u32>),
+    /// The bounds of both meshes, or `None` if they are empty.
+    pub bounds: Option<MeshBounds>
+}
+
+/// The bounds of a mesh, relative to its chunk,
+/// for example to skip chunks outside of the view with `camera::aabb_in_frustum`,
+/// after adding the position of the chunk.
+#[deriving(Clone, PartialEq, Show)]
+pub struct MeshBounds {
+    /// The lowest corner of the bounding box.
+    pub min: [f32, ..3],
+    /// The highest corner of the bounding box.
+    pub max: [f32, ..3],
+    /// The center of the bounding box and bounding sphere.
+    pub center: [f32, ..3],
+    /// The radius of the bounding sphere, which contains the bounding box.
+    pub radius: f32
+}
+
+// How a block is meshed.
+struct MeshedBlock<'a> {
+    textures: &'a BlockTextures,
+    shape: BlockShape,
+    // Whether each face hides the face of the neighbor on that side.
+    covers: [bool, ..6],
+    // Whether each face goes to the translucent mesh, in the same order as the faces.
+    translucent: [bool, ..6]
+}
+
+// Builds a mesh of a chunk.
+// `blocks` gives how a block is meshed, or `None` to skip it.
+fn build_mesh<'a, 'b>(
+    chunk: &Chunk<BlockId>,
+    atlas_uvs: &HashMap<String, [f32, ..4]>,
+    options: MeshOptions<'b>,
+    blocks: |BlockId| -> Option<MeshedBlock<'a>>
+) -> ChunkMesh {
+    let MeshOptions {
+        neighbors, light, mut rotations, mut tints, smooth_lighting, chunk_origin, ..
+    } = options;
+    let mut opaque = (vec![], vec![]);
+    let mut translucent = (vec![], vec![]);
+    for (x, y, z, &block) in chunk.iter_coords() {
+        if block == AIR {
+            continue;
+        }
+
+        let info = match blocks(block) {
+            Some(info) => info,
+            None => continue
+        };
+        let textures = info.textures;
+        let origin = [x as f32, y as f32, z as f32];
+        let [ox, oy, oz] = chunk_origin;
+        let world = [ox + x as i32, oy + y as i32, oz + z as i32];
+        let rect = |face: Face| match atlas_uvs.find_equiv(textures.get_at(face, world)) {
+            Some(rect) => *rect,
+            None => [0.0, 0.0, 0.0, 0.0]
+        };
+        let brightness = |pos: [i32, ..3]| {
+            let level = match light.and_then(|light| light.get_signed(pos)) {
+                Some(&level) => level,
+                None => MAX_LIGHT
+            };
+            level as f32 / MAX_LIGHT as f32
+        };
+        let face_color = |face: Face, brightness: f32| {
+            let [r, g, b] = match tints {
+                Some(ref mut tints) => (*tints)([x, y, z], face),
+                None => [1.0, 1.0, 1.0]
+            };
+            [r * brightness, g * brightness, b * brightness, 1.0]
+        };
+        let front = |face: Face| {
+            let [dx, dy, dz] = face.direction();
+            [x as i32 + dx, y as i32 + dy, z as i32 + dz]
+        };
+        // Which faces of the voxel at a position hide the faces of its neighbors.
+        let covering = |pos: [i32, ..3]| match neighbors.get(chunk, pos) {
+            Some(neighbor) if neighbor != AIR => match blocks(neighbor) {
+                Some(neighbor) => neighbor.covers,
+                None => [false, ..6]
+            },
+            Some(_) => [false, ..6],
+            None => [neighbors.unloaded_solid, ..6]
+        };
+        let same_block = |pos: [i32, ..3]| neighbors.get(chunk, pos) == Some(block);
+
+        match info.shape {
+            Cube => {
+                let rotation = match rotations {
+                    Some(ref mut rotations) => (*rotations)(x, y, z),
+                    None => BlockRotation::identity()
+                };
+                for source in FaceIterator::new() {
+                    let face = rotate(source, rotation);
+                    let is_translucent = info.translucent[source as uint];
+                    let hidden = covering(front(face))[face.opposite() as uint];
+                    if hidden || (is_translucent && same_block(front(face))) {
+                        continue;
+                    }
+
+                    let corners = face.vertices(origin, [1.0, 1.0, 1.0]);
+                    let uvs = tex_coords(rect(source), uv_rotation(source, rotation));
+                    let pos = [x as i32, y as i32, z as i32];
+                    let ao = face.ambient_occlusion(pos, |p| covering(p) == [true, ..6]);
+                    let mut levels = [brightness(front(face)), ..4];
+                    if smooth_lighting {
+                        let corner_neighbors = face.corner_neighbors(pos);
+                        for (level, samples) in levels.iter_mut().zip(corner_neighbors.iter()) {
+                            // Opaque voxels are not lit, and would darken the corner.
+                            let (mut sum, mut count) = (0.0, 0u);
+                            for &sample in samples.iter() {
+                                if covering(sample) != [true, ..6] {
+                                    sum += brightness(sample);
+                                    count += 1;
+                                }
+                            }
+                            *level = sum / count as f32;
+                        }
+                    }
+
+                    // Only the overlay is tinted, if there is one.
+                    let overlay = textures.overlay(source);
+                    let mut colors = [[0.0, ..4], ..4];
+                    for i in range(0u, 4) {
+                        colors[i] = match overlay {
+                            Some(_) => [levels[i], levels[i], levels[i], 1.0],
+                            None => face_color(face, levels[i])
+                        };
+                    }
+                    let target = if is_translucent { &mut translucent } else { &mut opaque };
+                    push_face(target, face, corners, uvs, colors, ao);
+
+                    match overlay {
+                        Some(name) => {
+                            let rect = match atlas_uvs.find_equiv(name) {
+                                Some(rect) => *rect,
+                                None => [0.0, 0.0, 0.0, 0.0]
+                            };
+                            let uvs = tex_coords(rect, uv_rotation(source, rotation));
+                            let (normal, mut raised) = (face.normal(), corners);
+                            for corner in raised.iter_mut() {
+                                for (c, n) in corner.iter_mut().zip(normal.iter()) {
+                                    *c += *n * OVERLAY_OFFSET;
+                                }
+                            }
+                            for i in range(0u, 4) {
+                                colors[i] = face_color(face, levels[i]);
+                            }
+                            push_face(target, face, raised, uvs, colors, ao);
+                        }
+                        None => {}
+                    }
+                }
+            }
+            Slab => {
+                let (min, max) = ([0.0, 0.0, 0.0], [1.0, 0.5, 1.0]);
+                let mut solid_neighbors = [false, ..6];
+                for face in FaceIterator::new() {
+                    solid_neighbors[face as uint] = covering(front(face))[face.opposite() as uint];
+                }
+                let visible = box_visible_faces(min, max, solid_neighbors);
+                for face in visible.iter() {
+                    let mut faces = FaceSet::empty();
+                    faces.insert(face);
+                    let mesh = box_geometry(origin, min, max, faces);
+                    let target = if info.translucent[face as uint] {
+                        &mut translucent
+                    } else {
+                        &mut opaque
+                    };
+                    let color = face_color(face, brightness(front(face)));
+                    append(target, mesh, Some(rect(face)), color);
+                }
+            }
+            Cross => {
+                let mesh = cross_mesh(origin, 1.0, rect(North), true);
+                let pos = [x as i32, y as i32, z as i32];
+                let target = if info.translucent[North as uint] {
+                    &mut translucent
+                } else {
+                    &mut opaque
+                };
+                append(target, mesh, None, face_color(North, brightness(pos)));
+            }
+        }
+    }
+    let bounds = {
+        let (ref opaque_vertices, _) = opaque;
+        let (ref translucent_vertices, _) = translucent;
+        mesh_bounds(opaque_vertices.iter().chain(translucent_vertices.iter()))
+    };
+    ChunkMesh {
+        opaque: opaque,
+        translucent: translucent,
+        bounds: bounds
+    }
+}
+
+// Computes the bounds of vertices, or `None` if there are none.
+fn mesh_bounds<'a, I: Iterator<&'a Vertex>>(mut vertices: I) -> Option<MeshBounds> {
+    let (mut min, mut max) = match vertices.next() {
+        Some(vertex) => (vertex.position, vertex.position),
+        None => return None
+    };
+    for vertex in vertices {
+        for i in range(0u, 3) {
+            min[i] = min[i].min(vertex.position[i]);
+            max[i] = max[i].max(vertex.position[i]);
+        }
+    }
+    let center = [(min[0] + max[0]) / 2.0, (min[1] + max[1]) / 2.0, (min[2] + max[2]) / 2.0];
+    Some(MeshBounds {
+        min: min,
+        max: max,
+        center: center,
+        radius: vec3_len(vec3_sub(max, center))
+    })
+}
+
+// How far overlay quads are raised above the face they cover, to avoid z-fighting.
+const OVERLAY_OFFSET: f32 = 0.001;
+
+// Appends a face of a cube to a mesh, with the corners in the same order as `Face::vertices`,
+// and their ambient occlusion levels from `Face::ambient_occlusion`.
+fn push_face(
+    target: &mut (Vec<Vertex>, Vec<u32>),
+    face: Face,
+    corners: [[f32, ..3], ..4],
+    uvs: [[f32, ..2], ..4],
+    colors: [[f32, ..4], ..4],
+    ao: [u8, ..4]
+) {
+    let (ref mut vertices, ref mut indices) = *target;
+    let start = vertices.len() as u32;
+    for i in range(0u, 4) {
+        vertices.push(Vertex {
+            position: corners[i],
+            normal: face.normal(),
+            uv: uvs[i],
+            texture: 0,
+            ao: ao[i] as f32 / 3.0,
+            color: colors[i]
+        });
+    }
+    let quad_indices = if flip_quad(ao) { FLIPPED_QUAD_INDICES } else { QUAD_INDICES };
+    for i in quad_indices.iter() {
+        indices.push(start + *i);
+    }
+}
+
+// Appends a mesh to another mesh, setting the vertex colors.
+// When a rectangle `[u0, v0, u1, v1]` of an atlas is given,
+// texture coordinates are mapped from `TEX_COORDS` space into it.
+fn append(
+    target: &mut (Vec<Vertex>, Vec<u32>),
+    mesh: (Vec<Vertex>, Vec<u32>),
+    rect: Option<[f32, ..4]>,
+    color: [f32, ..4]
+) {
+    let (ref mut vertices, ref mut indices) = *target;
+    let (mesh_vertices, mesh_indices) = mesh;
+    let start = vertices.len() as u32;
+    for vertex in mesh_vertices.into_iter() {
+        let uv = match rect {
+            Some([u0, v0, u1, v1]) => {
+                let [s, t] = vertex.uv;
+                [u0 + (u1 - u0) * s, v1 + (v0 - v1) * t]
+            }
+            None => vertex.uv
+        };
+        vertices.push(Vertex { uv: uv, color: color, ..vertex });
+    }
+    for i in mesh_indices.iter() {
+        indices.push(start + *i);
+    }
+}
+
+/// Writes a triangle mesh as a Wavefront OBJ file, with positions, normals and texture
+/// coordinates, for example to inspect a chunk mesh in a modeling tool.
+/// OBJ texture coordinates go up, so `v` is flipped to show the atlas the right way up.
+pub fn export_obj(vertices: &[Vertex], indices: &[u32], writer: &mut Writer) -> IoResult<()> {
+    for vertex in vertices.iter() {
+        let [x, y, z] = vertex.position;
+        try!(writeln!(writer, "v {} {} {}", x, y, z));
+    }
+    for vertex in vertices.iter() {
+        let [x, y, z] = vertex.normal;
+        try!(writeln!(writer, "vn {} {} {}", x, y, z));
+    }
+    for vertex in vertices.iter() {
+        let [u, v] = vertex.uv;
+        try!(writeln!(writer, "vt {} {}", u, 1.0 - v));
+    }
+
+    // Indices of OBJ files start at one.
+    for triangle in indices.chunks(3) {
+        try!(write!(writer, "f"));
+        for &i in triangle.iter() {
+            try!(write!(writer, " {0}/{0}/{0}", i + 1));
+        }
+        try!(writeln!(writer, ""));
+    }
+    Ok(())
+}
+
+/// Strips every attribute but the position from the vertices of a mesh,
+/// for example for a depth prepass before drawing the full mesh.
+/// The vertices stay in the same order, so the indices of the full mesh can be reused.
+pub fn positions_only(vertices: &[Vertex]) -> Vec<PositionVertex> {
+    vertices.iter().map(|vertex| PositionVertex { position: vertex.position }).collect()
+}
+
+/// Merges identical vertices of a mesh, and rewrites the indices to share them.
+///
+/// Vertices only merge when every attribute is identical,
+/// including texture coordinates, ambient occlusion and color,
+/// so the mesh looks the same.
+/// How many vertices are saved depends on the mesh:
+/// corners of adjacent faces only merge when their texture coordinates agree.
+pub fn weld(vertices: &[Vertex], indices: &[u32]) -> (Vec<Vertex>, Vec<u32>) {
+    let mut welded = vec![];
+    let mut unique = HashMap::new();
+    let mut remap = Vec::with_capacity(vertices.len());
+    for vertex in vertices.iter() {
+        let index = match unique.entry(vertex_key(vertex)) {
+            Occupied(entry) => *entry.get(),
+            Vacant(entry) => {
+                welded.push(vertex.clone());
+                *entry.set(welded.len() as u32 - 1)
+            }
+        };
+        remap.push(index);
+    }
+    (welded, indices.iter().map(|&i| remap[i as uint]).collect())
+}
+
+// Every attribute of a vertex, with floats as their exact mantissa, exponent and sign,
+// such that vertices can be hashed.
+fn vertex_key(vertex: &Vertex) -> (Vec<(u64, i16, i8)>, u32) {
+    let mut floats = vec![];
+    floats.push_all(vertex.position.as_slice());
+    floats.push_all(vertex.normal.as_slice());
+    floats.push_all(vertex.uv.as_slice());
+    floats.push(vertex.ao);
+    floats.push_all(vertex.color.as_slice());
+
+    (floats.iter().map(|f| f.integer_decode()).collect(), vertex.texture)
+}
+
+/// The chunks adjacent to a chunk, such that faces at its border can be culled.
+pub struct ChunkNeighbors<'a> {
+    /// The adjacent chunks in the same order as `cube::Face`, or `None` if not loaded.
+    pub chunks: [Option<&'a Chunk<BlockId>>, ..6],
+    /// Whether blocks in chunks that are not loaded are considered solid.
+    /// Solid hides faces towards unloaded chunks,
+    /// while air shows them, which avoids holes when the neighbor is loaded later.
+    pub unloaded_solid: bool
+}
+
+impl<'a> ChunkNeighbors<'a> {
+    /// Creates neighbors where no chunk is loaded, and unloaded chunks are air.
+    pub fn none() -> ChunkNeighbors<'a> {
+        ChunkNeighbors {
+            chunks: [None, ..6],
+            unloaded_solid: false
+        }
+    }
+
+    /// Gets the block at a position relative to a chunk, which may be in an adjacent chunk,
+    /// or `None` if the chunk containing it is not loaded.
+    /// Positions outside of the chunk along more than one axis are never loaded.
+    pub fn get(&self, chunk: &Chunk<BlockId>, pos: [i32, ..3]) -> Option<BlockId> {
+        let dims = chunk.dimensions();
+        let mut direction = [0i32, ..3];
+        for i in range(0u, 3) {
+            if pos[i] < 0 {
+                direction[i] = -1;
+            } else if pos[i] >= dims[i] as i32 {
+                direction[i] = 1;
+            }
+        }
+        if direction == [0, 0, 0] {
+            return chunk.get_signed(pos).map(|block| *block);
+        }
+
+        let neighbor = match Face::from_direction(direction) {
+            Some(face) => match self.chunks[face as uint] {
+                Some(neighbor) => neighbor,
+                None => return None
+            },
+            None => return None
+        };
+        let mut local = pos;
+        for i in range(0u, 3) {
+            if direction[i] < 0 {
+                local[i] += neighbor.dimensions()[i] as i32;
+            } else if direction[i] > 0 {
+                local[i] -= dims[i] as i32;
+            }
+        }
+        neighbor.get_signed(local).map(|block| *block)
+    }
+
+    /// Returns `true` if the block at a position relative to a chunk is solid.
+    pub fn is_solid(&self, chunk: &Chunk<BlockId>, pos: [i32, ..3]) -> bool {
+        match self.get(chunk, pos) {
+            Some(block) => block != AIR,
+            None => self.unloaded_solid
+        }
+    }
+}
+
+// Maps the corners of `TEX_COORDS` into a rectangle `[u0, v0, u1, v1]` of an atlas,
+// turning the texture by quarter turns.
+// The atlas rows go downwards, so the bottom of a face uses `v1`.
+fn tex_coords(rect: [f32, ..4], quarter_turns: u8) -> [[f32, ..2], ..4] {
+    let [u0, v0, u1, v1] = rect;
+    let mut coords = [[0.0, 0.0], ..4];
+    for (coord, &uv) in coords.iter_mut().zip(TEX_COORDS.iter()) {
+        let [s, t] = rotate_tex_coords(uv, quarter_turns);
+        *coord = [u0 + (u1 - u0) * s, v1 + (v0 - v1) * t];
+    }
+    coords
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::default::Default;
+    use std::io::MemWriter;
+    use std::num::Float;
+    use std::str;
+
+    use array::Chunk;
+    use block::{ BlockId, BlockInfo, BlockRegistry, BlockTextures, Cross, Cube, Slab };
+    use cube::{ East, Vertex, WHITE, West, cross_mesh, unit_cube };
+    use texture::Translucent;
+    use super::*;
+
+    // Counts the vertices with a normal on the plane `x = 1`, between the first two voxels.
+    fn count_at_x1(vertices: &[Vertex], normal: [f32, ..3]) -> uint {
+        vertices.iter().filter(|v| v.normal == normal && v.position[0] == 1.0).count()
+    }
+
+    #[test]
+    fn cube_next_to_slab_keeps_side_face() {
+        let mut chunk = Chunk::filled([2, 1, 1], 1u16);
+        chunk.set(1, 0, 0, 2);
+        let textures = BlockTextures::uniform("stone");
+        let options = MeshOptions {
+            shapes: Some(|block: BlockId| if block == 2 { Slab } else { Cube }),
+            ..Default::default()
+        };
+        let (vertices, _) = build_lit_chunk_mesh(&chunk, &HashMap::new(), |_| &textures, options)
+            .opaque;
+        assert_eq!(count_at_x1(vertices.as_slice(), [1.0, 0.0, 0.0]), 4);
+        assert_eq!(count_at_x1(vertices.as_slice(), [-1.0, 0.0, 0.0]), 0);
+    }
+
+    #[test]
+    fn cube_next_to_cube_culls_face() {
+        let chunk = Chunk::filled([2, 1, 1], 1u16);
+        let textures = BlockTextures::uniform("stone");
+        let (vertices, indices) = build_chunk_mesh(&chunk, &HashMap::new(), |_| &textures).opaque;
+        assert_eq!(count_at_x1(vertices.as_slice(), [1.0, 0.0, 0.0]), 0);
+        assert_eq!(count_at_x1(vertices.as_slice(), [-1.0, 0.0, 0.0]), 0);
+        assert_eq!((vertices.len(), indices.len()), (40, 60));
+    }
+
+    #[test]
+    fn occluder_darkens_two_corners() {
+        let mut chunk = Chunk::filled([2, 2, 1], 0u16);
+        chunk.set(0, 0, 0, 1);
+        chunk.set(1, 1, 0, 1);
+        let textures = BlockTextures::uniform("stone");
+        let (vertices, _) = build_chunk_mesh(&chunk, &HashMap::new(), |_| &textures).opaque;
+        let top: Vec<&Vertex> = vertices.iter()
+            .filter(|v| v.normal == [0.0, 1.0, 0.0] && v.position[1] == 1.0)
+            .collect();
+        assert_eq!(top.len(), 4);
+        for vertex in top.iter() {
+            // The corners along the occluder are darkened.
+            assert_eq!(vertex.ao < 1.0, vertex.position[0] == 1.0);
+        }
+    }
+
+    #[test]
+    fn adjacent_full_chunks_share_no_faces() {
+        let (a, b) = (Chunk::filled([2, 2, 2], 1u16), Chunk::filled([2, 2, 2], 1u16));
+        let textures = BlockTextures::uniform("stone");
+
+        let mut neighbors = ChunkNeighbors::none();
+        neighbors.chunks[East as uint] = Some(&b);
+        let options = MeshOptions { neighbors: neighbors, ..Default::default() };
+        let (vertices, _) = build_lit_chunk_mesh(&a, &HashMap::new(), |_| &textures, options)
+            .opaque;
+        assert!(vertices.iter().all(|v| v.normal != [1.0, 0.0, 0.0]));
+        assert_eq!(vertices.len(), 5 * 4 * 4);
+
+        let mut neighbors = ChunkNeighbors::none();
+        neighbors.chunks[West as uint] = Some(&a);
+        let options = MeshOptions { neighbors: neighbors, ..Default::default() };
+        let (vertices, _) = build_lit_chunk_mesh(&b, &HashMap::new(), |_| &textures, options)
+            .opaque;
+        assert!(vertices.iter().all(|v| v.normal != [-1.0, 0.0, 0.0]));
+    }
+
+    #[test]
+    fn cross_next_to_cube_hides_no_faces() {
+        let mut chunk = Chunk::filled([2, 1, 1], 1u16);
+        chunk.set(1, 0, 0, 2);
+        let textures = BlockTextures::uniform("grass");
+        let options = MeshOptions {
+            shapes: Some(|block: BlockId| if block == 2 { Cross } else { Cube }),
+            ..Default::default()
+        };
+        let uvs = HashMap::new();
+        let (vertices, indices) = build_lit_chunk_mesh(&chunk, &uvs, |_| &textures, options).opaque;
+
+        // Six faces of the cube and four quads of the double sided cross.
+        let (cross_vertices, cross_indices) = cross_mesh([0.0, 0.0, 0.0], 1.0, [0.0, ..4], true);
+        assert_eq!((cross_vertices.len(), cross_indices.len()), (4 * 4, 4 * 6));
+        assert_eq!((vertices.len(), indices.len()), ((6 + 4) * 4, (6 + 4) * 6));
+    }
+
+    #[test]
+    fn water_has_only_outer_translucent_faces() {
+        let mut registry = BlockRegistry::new();
+        registry.insert(1, BlockInfo {
+            textures: BlockTextures::uniform("water"),
+            shape: Cube,
+            is_opaque: false,
+            alpha_classes: [Translucent, ..6],
+            emits_light: 0
+        });
+        let chunk = Chunk::filled([2, 1, 1], 1u16);
+        let uvs = HashMap::new();
+        let mesh = build_registry_chunk_mesh(&chunk, &uvs, &registry, Default::default());
+
+        let (ref opaque, _) = mesh.opaque;
+        let (ref translucent, _) = mesh.translucent;
+        assert!(opaque.is_empty());
+        assert_eq!(count_at_x1(translucent.as_slice(), [1.0, 0.0, 0.0]), 0);
+        assert_eq!(count_at_x1(translucent.as_slice(), [-1.0, 0.0, 0.0]), 0);
+        assert_eq!(translucent.len(), 10 * 4);
+    }
+
+    #[test]
+    fn single_voxel_bounds() {
+        let mut chunk = Chunk::filled([4, 4, 4], 0u16);
+        chunk.set(1, 2, 3, 1);
+        let textures = BlockTextures::uniform("stone");
+        let bounds = build_chunk_mesh(&chunk, &HashMap::new(), |_| &textures).bounds.unwrap();
+        assert_eq!(bounds.min, [1.0, 2.0, 3.0]);
+        assert_eq!(bounds.max, [2.0, 3.0, 4.0]);
+        assert_eq!(bounds.center, [1.5, 2.5, 3.5]);
+        assert!((bounds.radius - 0.75f32.sqrt()).abs() < 1e-6);
+    }
+
+    #[test]
+    fn empty_chunk_has_no_bounds() {
+        let chunk = Chunk::filled([4, 4, 4], 0u16);
+        let textures = BlockTextures::uniform("stone");
+        let mesh = build_chunk_mesh(&chunk, &HashMap::new(), |_| &textures);
+        let (ref vertices, _) = mesh.opaque;
+        assert!(vertices.is_empty());
+        assert!(mesh.bounds.is_none());
+    }
+
+    #[test]
+    fn weld_keeps_faces_with_atlas_coordinates() {
+        // Each face spans the whole tile, so the corners shared by adjacent faces
+        // have opposite texture coordinates and nothing merges.
+        let chunk = Chunk::filled([2, 2, 2], 1u16);
+        let textures = BlockTextures::uniform("stone");
+        let mut atlas_uvs = HashMap::new();
+        atlas_uvs.insert("stone".to_string(), [0.0, 0.0, 0.5, 0.5]);
+        let (vertices, indices) = build_chunk_mesh(&chunk, &atlas_uvs, |_| &textures).opaque;
+        let (welded, welded_indices) = weld(vertices.as_slice(), indices.as_slice());
+        assert_eq!(vertices.len(), 6 * 4 * 4);
+        assert_eq!(welded.len(), vertices.len());
+        assert_eq!(welded_indices, indices);
+    }
+
+    #[test]
+    fn weld_merges_shared_corners() {
+        // Two coplanar quads with planar texture coordinates share an edge.
+        let vertex = |x: f32, y: f32| Vertex {
+            position: [x, y, 0.0],
+            normal: [0.0, 0.0, 1.0],
+            uv: [x, y],
+            texture: 0,
+            ao: 1.0,
+            color: WHITE
+        };
+        let vertices = vec![vertex(0.0, 0.0), vertex(1.0, 0.0), vertex(1.0, 1.0), vertex(0.0, 1.0),
+            vertex(1.0, 0.0), vertex(2.0, 0.0), vertex(2.0, 1.0), vertex(1.0, 1.0)];
+        let indices = vec![0, 1, 2, 0, 2, 3, 4, 5, 6, 4, 6, 7];
+        let (welded, welded_indices) = weld(vertices.as_slice(), indices.as_slice());
+        assert_eq!(welded.len(), 6);
+        assert_eq!(welded_indices, vec![0, 1, 2, 0, 2, 3, 1, 4, 5, 1, 5, 2]);
+        for (&i, &j) in indices.iter().zip(welded_indices.iter()) {
+            assert_eq!(vertices[i as uint], welded[j as uint]);
+        }
+    }
+
+    #[test]
+    fn lod_mesh_of_full_chunk_is_closed() {
+        let chunk = Chunk::filled([4, 4, 4], 1u16);
+        let textures = BlockTextures::uniform("stone");
+        let (vertices, indices) = build_lod_chunk_mesh(&chunk, 2, &HashMap::new(), |_| &textures)
+            .opaque;
+        // Four cells of two voxels per side of the chunk.
+        assert_eq!(vertices.len(), 6 * 4 * 4);
+
+        // Every edge of a closed mesh is shared by exactly two triangles.
+        let corner = |i: u32| {
+            let [x, y, z] = vertices[i as uint].position;
+            (x as i32, y as i32, z as i32)
+        };
+        let mut edges = HashMap::new();
+        for triangle in indices.as_slice().chunks(3) {
+            for i in range(0u, 3) {
+                let (a, b) = (corner(triangle[i]), corner(triangle[(i + 1) % 3]));
+                let edge = if a < b { (a, b) } else { (b, a) };
+                let count = match edges.get(&edge) {
+                    Some(&count) => count,
+                    None => 0u
+                };
+                edges.insert(edge, count + 1);
+            }
+        }
+        assert!(edges.values().all(|&count| count == 2));
+    }
+
+    #[test]
+    fn smooth_lighting_follows_light_gradient() {
+        let mut chunk = Chunk::filled([3, 3, 2], 0u16);
+        chunk.set(1, 1, 1, 1);
+        let mut light = Chunk::filled([3, 3, 2], 0u8);
+        for (x, y, z, _) in chunk.iter_coords() {
+            light.set(x, y, z, 5 * x as u8);
+        }
+        let textures = BlockTextures::uniform("stone");
+        let north_colors = |smooth_lighting: bool| {
+            let options = MeshOptions {
+                light: Some(&light),
+                smooth_lighting: smooth_lighting,
+                ..Default::default()
+            };
+            let uvs = HashMap::new();
+            let (vertices, _) = build_lit_chunk_mesh(&chunk, &uvs, |_| &textures, options).opaque;
+            vertices.iter()
+                .filter(|v| v.normal == [0.0, 0.0, -1.0])
+                .map(|v| (v.position[0], v.color[0]))
+                .collect::<Vec<(f32, f32)>>()
+        };
+
+        let smooth = north_colors(true);
+        assert_eq!(smooth.len(), 4);
+        for &(x, color) in smooth.iter() {
+            for &(other_x, other_color) in smooth.iter() {
+                if x < other_x {
+                    assert!(color < other_color);
+                }
+            }
+        }
+
+        let flat = north_colors(false);
+        assert_eq!(flat.len(), 4);
+        let (_, first) = flat[0];
+        assert!(flat.iter().all(|&(_, color)| color == first));
+    }
+
+    #[test]
+    fn obj_of_unit_cube_has_a_line_per_vertex_and_triangle() {
+        let (vertices, indices) = unit_cube();
+        let mut writer = MemWriter::new();
+        export_obj(vertices.as_slice(), indices.as_slice(), &mut writer).unwrap();
+        let obj = str::from_utf8(writer.get_ref()).unwrap();
+        let count = |prefix: &str| obj.lines().filter(|line| line.starts_with(prefix)).count();
+        assert_eq!(count("v "), 24);
+        assert_eq!(count("vn "), 24);
+        assert_eq!(count("vt "), 24);
+        assert_eq!(count("f "), 12);
+    }
+}
@@ -0,0 +1,124 @@
+//! Cast rays through chunks, for example for picking blocks.
+
+use std::f32::INFINITY;
+
+use array::Chunk;
+use cube::{ Face, Down, Up, North, South, West, East };
+use vecmath::{ Vector3, vec3_len, vec3_scale };
+
+/// A voxel hit by a ray.
+#[deriving(Clone, PartialEq, Show)]
+pub struct RayHit {
+    /// Position of the voxel in the chunk.
+    pub voxel: [u32, ..3],
+    /// Face through which the ray entered the voxel.
+    /// A new block placed against the voxel goes in this direction.
+    pub face: Face,
+    /// Distance from the origin of the ray to the entry point.
+    pub distance: f32
+}
+
+/// Finds the first solid voxel along a ray,
+/// using the traversal algorithm of Amanatides and Woo.
+///
+/// The distance is measured in voxels, whatever the length of `dir`.
+/// Voxels outside the chunk are empty, so `max_dist` should be finite.
+/// If the ray starts inside a solid voxel, that voxel is hit at distance zero,
+/// through the face opposite to the main direction of the ray.
+pub fn raycast<T>(
+    chunk: &Chunk<T>,
+    origin: Vector3<f32>,
+    dir: Vector3<f32>,
+    max_dist: f32,
+    is_solid: |&T| -> bool
+) -> Option<RayHit> {
+    let len = vec3_len(dir);
+    if len == 0.0 {
+        return None;
+    }
+    let dir = vec3_scale(dir, 1.0 / len);
+
+    let mut voxel = [origin[0].floor() as i32, origin[1].floor() as i32, origin[2].floor() as i32];
+    let mut step = [0i32, ..3];
+    // Distance along the ray to the next voxel boundary on each axis.
+    let mut t_max = [INFINITY, ..3];
+    // Distance along the ray between two voxel boundaries on each axis.
+    let mut t_delta = [INFINITY, ..3];
+    for i in range(0u, 3) {
+        if dir[i] > 0.0 {
+            step[i] = 1;
+            t_delta[i] = 1.0 / dir[i];
+            t_max[i] = (voxel[i] as f32 + 1.0 - origin[i]) / dir[i];
+        } else if dir[i] < 0.0 {
+            step[i] = -1;
+            t_delta[i] = -1.0 / dir[i];
+            t_max[i] = (voxel[i] as f32 - origin[i]) / dir[i];
+        }
+    }
+
+    let main_axis = if dir[0].abs() >= dir[1].abs() && dir[0].abs() >= dir[2].abs() {
+        0
+    } else if dir[1].abs() >= dir[2].abs() {
+        1
+    } else {
+        2
+    };
+    let mut face = entry_face(main_axis, step[main_axis]);
+    let mut distance = 0.0;
+    loop {
+        match chunk.get_signed(voxel) {
+            Some(v) if is_solid(v) => {
+                return Some(RayHit {
+                    voxel: [voxel[0] as u32, voxel[1] as u32, voxel[2] as u32],
+                    face: face,
+                    distance: distance
+                });
+            }
+            _ => {}
+        }
+
+        // Step to the nearest voxel boundary.
+        let axis = if t_max[0] < t_max[1] {
+            if t_max[0] < t_max[2] { 0 } else { 2 }
+        } else {
+            if t_max[1] < t_max[2] { 1 } else { 2 }
+        };
+        distance = t_max[axis];
+        if distance > max_dist {
+            return None;
+        }
+        voxel[axis] += step[axis];
+        t_max[axis] += t_delta[axis];
+        face = entry_face(axis, step[axis]);
+    }
+}
+
+// Gets the face through which a ray enters a voxel when stepping along an axis.
+fn entry_face(axis: uint, step: i32) -> Face {
+    match (axis, step > 0) {
+        (0, true) => West,
+        (0, false) => East,
+        (1, true) => Down,
+        (1, false) => Up,
+        (_, true) => North,
+        (_, false) => South
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use array::Chunk;
+    use cube::West;
+    use super::*;
+
+    #[test]
+    fn ray_hits_first_solid_voxel() {
+        let mut chunk = Chunk::filled([4, 4, 4], false);
+        chunk.set(2, 0, 0, true);
+        let hit = raycast(&chunk, [0.5, 0.5, 0.5], [1.0, 0.0, 0.0], 10.0, |&solid| solid);
+        assert_eq!(hit, Some(RayHit { voxel: [2, 0, 0], face: West, distance: 1.5 }));
+
+        let miss = raycast(&chunk, [0.5, 1.5, 0.5], [1.0, 0.0, 0.0], 10.0, |&solid| solid);
+        assert_eq!(miss, None);
+    }
+}
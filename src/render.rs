@@ -0,0 +1,404 @@
+//! Rendering helpers, e.g. for drawing chunk meshes or baking block icons.
+//!
+//! This module is compiled with the `render` feature, which is enabled by default.
+
+use std::default::Default;
+use std::mem;
+
+use glium;
+use glium::{ Display, DrawError, DrawParameters, IndexBuffer, Program, Surface, VertexBuffer };
+use glium::index_buffer::TrianglesList;
+use glium::framebuffer::SimpleFrameBuffer;
+use glium::render_buffer::DepthRenderBuffer;
+use glium::uniforms::Uniforms;
+use image::{ GenericImage, ImageBuf, Rgba };
+use vecmath::{ Matrix4, col_mat4_mul, vec3_scale, vec3_sub };
+
+use camera::Camera;
+use cube::{ QUAD_INDICES, Vertex };
+use texture::{ Atlas, Texture2d };
+
+/// The yaw of an isometric view, in radians.
+pub const ISOMETRIC_YAW: f32 = -0.78539816;
+
+/// The pitch of an isometric view, looking down, in radians.
+pub const ISOMETRIC_PITCH: f32 = -0.61547971;
+
+const VERTEX_SHADER: &'static str = "
+    #version 110
+
+    uniform mat4 matrix;
+
+    attribute vec3 position;
+    attribute vec3 normal;
+    attribute vec2 uv;
+    attribute vec4 color;
+
+    varying vec3 v_normal;
+    varying vec2 v_uv;
+    varying vec4 v_color;
+
+    void main() {
+        v_normal = normal;
+        v_uv = uv;
+        v_color = color;
+        gl_Position = matrix * vec4(position, 1.0);
+    }
+";
+
+const FRAGMENT_SHADER: &'static str = "
+    #version 110
+
+    uniform sampler2D atlas;
+
+    varying vec3 v_normal;
+    varying vec2 v_uv;
+    varying vec4 v_color;
+
+    void main() {
+        vec4 color = texture2D(atlas, v_uv) * v_color;
+        if (color.a < 0.5) {
+            discard;
+        }
+
+        // Shade the faces differently, such that edges of the block are visible.
+        vec3 light = normalize(vec3(0.3, 1.0, 0.6));
+        float shade = 0.6 + 0.4 * max(dot(normalize(v_normal), light), 0.0);
+        gl_FragColor = vec4(color.rgb * shade, color.a);
+    }
+";
+
+/// Compiles the built-in program for meshes of `cube::Vertex`,
+/// which multiplies the atlas with the vertex color,
+/// discards mostly transparent pixels for cutout tiles,
+/// and shades faces from a fixed light direction.
+/// The uniforms are the `matrix` from world to clip space, and the `atlas` texture.
+pub fn program(display: &Display) -> Program {
+    Program::from_source(display, VERTEX_SHADER, FRAGMENT_SHADER, None).unwrap()
+}
+
+/// Draw parameters suited to voxels, with depth testing and back faces culled.
+/// Front faces of the meshes of this crate are clockwise.
+/// The target needs a depth buffer.
+pub fn voxel_draw_parameters() -> DrawParameters {
+    DrawParameters {
+        depth_function: glium::IfLess,
+        backface_culling: glium::CullCounterClockWise,
+        .. Default::default()
+    }
+}
+
+/// Draws a mesh, for example a chunk, with the built-in `program`
+/// and `voxel_draw_parameters`.
+/// `view_proj` transforms world positions to clip space, for example
+/// `col_mat4_mul(projection, camera.view_matrix())`.
+///
+/// ```ignore
+/// let program = render::program(&display);
+/// let mut frame = display.draw();
+/// frame.clear_color_and_depth((0.5, 0.7, 1.0, 1.0), 1.0);
+/// render::draw_mesh(&mut frame, &program, &mesh, &atlas.texture, view_proj).unwrap();
+/// frame.finish();
+/// ```
+pub fn draw_mesh<S: Surface>(
+    target: &mut S,
+    program: &Program,
+    mesh: &(VertexBuffer<Vertex>, IndexBuffer),
+    atlas: &Texture2d,
+    view_proj: Matrix4<f32>
+) -> Result<(), DrawError> {
+    let (ref vertices, ref indices) = *mesh;
+    let uniforms = uniform! {
+        matrix: view_proj,
+        atlas: atlas
+    };
+    target.draw(vertices, indices, program, &uniforms, &voxel_draw_parameters())
+}
+
+/// Renders a mesh to an image of `size` by `size` pixels, e.g. an inventory icon.
+///
+/// The mesh is expected to fit in the unit cube, like `cube::unit_cube`,
+/// with texture coordinates into the atlas, like the output of `mesh::build_chunk_mesh`.
+/// The camera looks at the center of the cube from a yaw and pitch in radians,
+/// for example `ISOMETRIC_YAW` and `ISOMETRIC_PITCH`,
+/// using an orthographic projection.
+/// The background is transparent.
+pub fn bake_icon(
+    display: &Display,
+    vertices: &VertexBuffer<Vertex>,
+    indices: &IndexBuffer,
+    atlas: &Texture2d,
+    yaw: f32,
+    pitch: f32,
+    size: u32
+) -> ImageBuf<Rgba<u8>> {
+    let program = program(display);
+
+    let center = [0.5, 0.5, 0.5];
+    let mut camera = Camera::new(center);
+    camera.yaw = yaw;
+    camera.pitch = pitch;
+    camera.position = vec3_sub(center, vec3_scale(camera.forward(), 2.0));
+
+    // Half the diagonal of the unit cube, such that it fits from any angle.
+    let extent = 0.87;
+    let matrix = col_mat4_mul(orthographic(extent, 0.1, 4.0), camera.view_matrix());
+
+    let color = Texture2d::new_empty(display, glium::texture::U8U8U8U8, size, size);
+    let depth = DepthRenderBuffer::new(display, glium::texture::F32, size, size);
+    {
+        let mut target = SimpleFrameBuffer::with_depth_buffer(display, &color, &depth);
+        target.clear_color_and_depth((0.0, 0.0, 0.0, 0.0), 1.0);
+
+        let uniforms = uniform! {
+            matrix: matrix,
+            atlas: atlas
+        };
+        let params = DrawParameters {
+            depth_function: glium::IfLess,
+            .. Default::default()
+        };
+        target.draw(vertices, indices, &program, &uniforms, &params).unwrap();
+    }
+
+    // The first row read from the texture is the bottom of the image.
+    let pixels: ImageBuf<Rgba<u8>> = color.read();
+    ImageBuf::from_fn(size, size, |x, y| pixels.get_pixel(x, size - 1 - y))
+}
+
+// Computes a column major orthographic projection matrix,
+// showing `[-extent, extent]` horizontally and vertically.
+fn orthographic(extent: f32, near: f32, far: f32) -> Matrix4<f32> {
+    [
+        [1.0 / extent, 0.0, 0.0, 0.0],
+        [0.0, 1.0 / extent, 0.0, 0.0],
+        [0.0, 0.0, 2.0 / (near - far), 0.0],
+        [0.0, 0.0, (far + near) / (near - far), 1.0]
+    ]
+}
+
+/// Per-instance attributes of a cube drawn with `draw_instanced`.
+///
+/// Each vertex of the shared cube is transformed as
+/// `position * scale + offset` and `uv * uv_scale + uv_offset`,
+/// see `INSTANCED_VERTEX_SHADER`.
+#[deriving(Clone, PartialEq, Show)]
+pub struct CubeInstance {
+    /// Position of the lowest corner of the cube.
+    pub offset: [f32, ..3],
+    /// Size of the cube.
+    pub scale: f32,
+    /// Offset added to texture coordinates, for example the corner of a tile in an atlas.
+    pub uv_offset: [f32, ..2],
+    /// Scale of texture coordinates, for example the size of a tile in an atlas.
+    pub uv_scale: [f32, ..2]
+}
+
+implement_vertex!(CubeInstance, offset, scale, uv_offset, uv_scale)
+
+/// A vertex shader for `draw_instanced`,
+/// with the attributes of `cube::Vertex` and `CubeInstance`.
+/// Passes the normal, texture coordinates and color to the fragment shader
+/// as `v_normal`, `v_uv` and `v_color`.
+pub const INSTANCED_VERTEX_SHADER: &'static str = "
+    #version 110
+
+    uniform mat4 matrix;
+
+    attribute vec3 position;
+    attribute vec3 normal;
+    attribute vec2 uv;
+    attribute vec4 color;
+
+    attribute vec3 offset;
+    attribute float scale;
+    attribute vec2 uv_offset;
+    attribute vec2 uv_scale;
+
+    varying vec3 v_normal;
+    varying vec2 v_uv;
+    varying vec4 v_color;
+
+    void main() {
+        v_normal = normal;
+        v_uv = uv * uv_scale + uv_offset;
+        v_color = color;
+        gl_Position = matrix * vec4(position * scale + offset, 1.0);
+    }
+";
+
+/// Draws a cube once per instance in a single draw call,
+/// for example for particles or distant blocks.
+///
+/// `cube` is shared by every instance, for example from `cube::mesh`,
+/// and `instances` can be filled again every frame.
+/// The program must use the per-instance attributes of `CubeInstance`,
+/// for example with `INSTANCED_VERTEX_SHADER`.
+/// Will fail if the display does not support instancing.
+pub fn draw_instanced<S: Surface, U: Uniforms>(
+    target: &mut S,
+    cube: &VertexBuffer<Vertex>,
+    indices: &IndexBuffer,
+    instances: &VertexBuffer<CubeInstance>,
+    program: &Program,
+    uniforms: &U,
+    params: &DrawParameters
+) -> Result<(), DrawError> {
+    target.draw((cube, instances.per_instance().unwrap()), indices, program, uniforms, params)
+}
+
+/// A vertex of a quad drawn by `SpriteBatch`.
+#[deriving(Clone, PartialEq, Show)]
+pub struct SpriteVertex {
+    /// Position in pixels, from the top left corner of the target.
+    pub position: [f32, ..2],
+    /// Texture coordinates in the atlas.
+    pub uv: [f32, ..2],
+    /// Color multiplied with the texture.
+    pub color: [f32, ..4]
+}
+
+implement_vertex!(SpriteVertex, position, uv, color)
+
+const SPRITE_VERTEX_SHADER: &'static str = "
+    #version 110
+
+    uniform mat4 matrix;
+
+    attribute vec2 position;
+    attribute vec2 uv;
+    attribute vec4 color;
+
+    varying vec2 v_uv;
+    varying vec4 v_color;
+
+    void main() {
+        v_uv = uv;
+        v_color = color;
+        gl_Position = matrix * vec4(position, 0.0, 1.0);
+    }
+";
+
+const SPRITE_FRAGMENT_SHADER: &'static str = "
+    #version 110
+
+    uniform sampler2D atlas;
+
+    varying vec2 v_uv;
+    varying vec4 v_color;
+
+    void main() {
+        gl_FragColor = texture2D(atlas, v_uv) * v_color;
+    }
+";
+
+/// Collects textured quads from an atlas and draws them in a single draw call,
+/// for example for a HUD or debug text.
+///
+/// ```ignore
+/// let mut batch = SpriteBatch::new(&display);
+/// batch.push_tile(&atlas, "heart", [8.0, 8.0, 16.0, 16.0], [1.0, 1.0, 1.0, 1.0]);
+/// batch.flush(&display, &mut target, &atlas.texture).unwrap();
+/// ```
+pub struct SpriteBatch {
+    program: Program,
+    // Four vertices per quad, in the same order as `cube::TEX_COORDS`.
+    vertices: Vec<SpriteVertex>
+}
+
+impl SpriteBatch {
+    /// Creates an empty batch.
+    pub fn new(display: &Display) -> SpriteBatch {
+        SpriteBatch {
+            program: Program::from_source(display, SPRITE_VERTEX_SHADER, SPRITE_FRAGMENT_SHADER,
+                None).unwrap(),
+            vertices: vec![]
+        }
+    }
+
+    /// Adds a quad covering `dest`, `[x, y, width, height]` in pixels from the top left,
+    /// with the texture coordinates of its corners in the same order as `cube::TEX_COORDS`,
+    /// for example from `Atlas::uv`.
+    pub fn push(&mut self, uv: [[f32, ..2], ..4], dest: [f32, ..4], color: [f32, ..4]) {
+        let [x, y, w, h] = dest;
+        let corners = [[x + w, y + h], [x, y + h], [x, y], [x + w, y]];
+        for (position, uv) in corners.iter().zip(uv.iter()) {
+            self.vertices.push(SpriteVertex {
+                position: *position,
+                uv: *uv,
+                color: color
+            });
+        }
+    }
+
+    /// Adds a quad showing a tile of an atlas, see `push`.
+    /// Returns `false` and adds nothing if the tile is not in the atlas.
+    pub fn push_tile(
+        &mut self,
+        atlas: &Atlas,
+        name: &str,
+        dest: [f32, ..4],
+        color: [f32, ..4]
+    ) -> bool {
+        match atlas.uv(name) {
+            Some(uv) => { self.push(uv, dest, color); true }
+            None => false
+        }
+    }
+
+    /// Returns the number of quads waiting to be drawn.
+    pub fn len(&self) -> uint {
+        self.vertices.len() / 4
+    }
+
+    /// Discards the quads without drawing them.
+    pub fn clear(&mut self) {
+        self.vertices.clear();
+    }
+
+    /// Draws every quad with alpha blending and without depth testing,
+    /// in the order they were added, and empties the batch.
+    /// Positions are mapped to the dimensions of the target.
+    pub fn flush<S: Surface>(
+        &mut self,
+        display: &Display,
+        target: &mut S,
+        atlas: &Texture2d
+    ) -> Result<(), DrawError> {
+        if self.vertices.is_empty() {
+            return Ok(());
+        }
+
+        let mut indices = Vec::with_capacity(self.len() * QUAD_INDICES.len());
+        for quad in range(0, self.len() as u32) {
+            for i in QUAD_INDICES.iter() {
+                indices.push(quad * 4 + *i);
+            }
+        }
+        let vertices = mem::replace(&mut self.vertices, vec![]);
+        let vertices = VertexBuffer::new(display, vertices);
+        let indices = IndexBuffer::new(display, TrianglesList(indices));
+
+        let (width, height) = target.get_dimensions();
+        let uniforms = uniform! {
+            matrix: pixels(width as f32, height as f32),
+            atlas: atlas
+        };
+        let params = DrawParameters {
+            blending_function: Some(glium::LerpBySourceAlpha),
+            .. Default::default()
+        };
+        target.draw(&vertices, &indices, &self.program, &uniforms, &params)
+    }
+}
+
+// Computes a column major projection matrix from pixels,
+// with the origin in the top left corner and rows going down.
+fn pixels(width: f32, height: f32) -> Matrix4<f32> {
+    [
+        [2.0 / width, 0.0, 0.0, 0.0],
+        [0.0, -2.0 / height, 0.0, 0.0],
+        [0.0, 0.0, 1.0, 0.0],
+        [-1.0, 1.0, 0.0, 1.0]
+    ]
+}
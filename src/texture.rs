@@ -1,68 +1,793 @@
 //! Create textures and build texture atlas.
 
-use glium::Display;
+use glium::{ Display, Rect };
+use glium::uniforms::{ MagnifySamplerFilter, MinifySamplerFilter, Sampler, SamplerBehavior,
+    SamplerWrapFunction };
 use image;
-use image::{ GenericImage, ImageBuf, MutableRefImage, Pixel, Rgba, SubImage };
-use std::collections::HashMap;
-use std::collections::hash_map::{ Occupied, Vacant };
+use image::{ DynamicImage, FilterType, GenericImage, ImageBuf, ImageResult, Pixel, Rgb, Rgba,
+    SubImage };
+use image::imageops;
+use std::cmp;
+use std::collections::{ HashMap, RingBuf };
+use std::collections::hash_map::{ Keys, Occupied, Vacant };
+use std::default::Default;
+use std::error::Error;
+use std::fmt;
+use std::hash::Hash;
+use std::hash::sip::SipState;
+use std::from_str::from_str;
+use std::io::{ File, InvalidInput, IoError, IoResult, OtherIoError };
 use std::mem;
 
-pub use glium::Texture2d;
+pub use glium::{ SrgbTexture2d, Texture2d, Texture2dArray };
+
+/// An error when loading textures or building an atlas.
+#[deriving(Clone, PartialEq)]
+pub enum TextureError {
+    /// A file could not be read, with its path and the I/O error.
+    Io(String, IoError),
+    /// An image could not be decoded,
+    /// with the source of the image and the error of the decoder.
+    Decode(String, String),
+    /// An image does not have the expected size,
+    /// with the source of the image and the expected and found dimensions.
+    WrongDimensions(String, (u32, u32), (u32, u32)),
+    /// A sprite sheet is not exactly the expected number of tiles, with the source of the sheet,
+    /// the expected columns and rows, the tile size and the found dimensions.
+    SheetDimensions(String, (u32, u32), (u32, u32), (u32, u32)),
+    /// A tile size is zero, with the tile width and height.
+    ZeroTileSize(u32, u32),
+    /// A tile does not have the tile width, or its height is not a multiple of the tile height,
+    /// with the source of the tile, the tile size and the found dimensions.
+    TileSizeMismatch(String, (u32, u32), (u32, u32)),
+    /// A color map image is empty, with its source if known.
+    EmptyImage(Option<String>),
+    /// Adding a tile would grow the atlas beyond its maximum size,
+    /// with the name of the tile and the maximum width and height.
+    AtlasFull(String, u32),
+    /// A tile could not be loaded while loading several tiles, with its name.
+    Tile(String, Box<TextureError>),
+    /// A manifest could not be parsed, with its source and the reason.
+    Manifest(String, String)
+}
+
+impl fmt::Show for TextureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Io(ref source, ref e) => write!(f, "Could not load '{}': {}", source, e),
+            Decode(ref source, ref e) => write!(f, "Could not load '{}': {}", source, e),
+            WrongDimensions(ref source, (ew, eh), (w, h)) => {
+                write!(f, "Expected an image of {}x{}, found {}x{} in '{}'", ew, eh, w, h, source)
+            }
+            SheetDimensions(ref source, (cols, rows), (uw, uh), (w, h)) => {
+                write!(f, "Expected a sheet of {}x{} tiles of {}x{}, found {}x{} in '{}'",
+                    cols, rows, uw, uh, w, h, source)
+            }
+            ZeroTileSize(w, h) => write!(f, "Tile size must not be zero, found {}x{}", w, h),
+            TileSizeMismatch(ref source, (uw, _), (w, _)) if w != uw => {
+                write!(f, "Expected tile width {}, found {} in '{}'", uw, w, source)
+            }
+            TileSizeMismatch(ref source, (_, uh), (_, h)) => {
+                write!(f, "Expected tile height to be a multiple of {}, found {} in '{}'",
+                    uh, h, source)
+            }
+            EmptyImage(None) => write!(f, "ColorMap expected a non-empty image"),
+            EmptyImage(Some(ref source)) => {
+                write!(f, "ColorMap expected a non-empty image in '{}'", source)
+            }
+            AtlasFull(ref name, max_size) => {
+                write!(f, "Atlas would exceed the maximum size of {}x{} when loading '{}'",
+                    max_size, max_size, name)
+            }
+            Tile(ref name, ref e) => write!(f, "Could not load tile '{}': {}", name, e),
+            Manifest(ref source, ref e) => write!(f, "Invalid manifest '{}': {}", source, e)
+        }
+    }
+}
+
+impl Error for TextureError {
+    fn description(&self) -> &str {
+        match *self {
+            Io(..) => "could not read file",
+            Decode(..) => "could not load image",
+            WrongDimensions(..) => "wrong image dimensions",
+            SheetDimensions(..) => "wrong sheet dimensions",
+            ZeroTileSize(..) => "zero tile size",
+            TileSizeMismatch(..) => "wrong tile dimensions",
+            EmptyImage(..) => "empty color map",
+            AtlasFull(..) => "atlas exceeds maximum size",
+            Tile(..) => "could not load tile",
+            Manifest(..) => "invalid manifest"
+        }
+    }
+
+    fn detail(&self) -> Option<String> {
+        Some(self.to_string())
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        match *self {
+            Io(_, ref e) => Some(e as &Error),
+            Tile(_, ref e) => Some(&**e as &Error),
+            _ => None
+        }
+    }
+}
+
+/// Uploads an image to a texture, for example the image of an atlas
+/// built on another thread with `AtlasBuilder::into_image`.
+pub fn upload(d: &Display, img: ImageBuf<Rgba<u8>>) -> Texture2d {
+    Texture2d::new(d, img)
+}
+
+/// Samples a lookup table from `ColorMap::bake_lut` at the nearest entry,
+/// with the same coordinates as `ColorMap::get`.
+pub fn sample_lut(lut: &[[u8, ..3]], resolution: u32, x: f32, y: f32) -> [u8, ..3] {
+    let scale = cmp::max(resolution, 1) as f32 - 1.0;
+    let i = (x.max(0.0).min(1.0) * scale + 0.5) as uint;
+    let j = (y.max(0.0).min(1.0) * scale + 0.5) as uint;
+    lut[j * resolution as uint + i]
+}
 
 /// Loads RGBA image from path.
-fn load_rgba8(path: &Path) -> Result<ImageBuf<Rgba<u8>>, String> {
-    Ok(match image::open(path) {
+/// The file is read before decoding, so that a missing file is an `Io` error.
+fn load_rgba8(path: &Path) -> Result<ImageBuf<Rgba<u8>>, TextureError> {
+    let source = path.display().to_string();
+    let bytes = match File::open(path).read_to_end() {
+        Ok(bytes) => bytes,
+        Err(e) => return Err(Io(source, e))
+    };
+    load_rgba8_from_memory(bytes.as_slice(), source.as_slice())
+}
+
+/// Loads RGBA image from encoded bytes.
+fn load_rgba8_from_memory(bytes: &[u8], source: &str) -> Result<ImageBuf<Rgba<u8>>, TextureError> {
+    to_rgba8(image::load_from_memory(bytes), source)
+}
+
+/// Converts a decoded image of any color type to RGBA.
+/// The source is used in error messages.
+fn to_rgba8(
+    img: ImageResult<DynamicImage>,
+    source: &str
+) -> Result<ImageBuf<Rgba<u8>>, TextureError> {
+    Ok(match img {
         Ok(image::ImageRgba8(img)) => img,
-        Ok(image::ImageRgb8(img)) => {
+        Ok(img) => {
+            // Any other color type, such as grayscale or RGB,
+            // expanded through the generic pixel accessors.
             let (w, h) = img.dimensions();
             ImageBuf::from_fn(w, h, |x, y| img.get_pixel(x, y).to_rgba())
         }
-        Ok(img) => {
-            return Err(format!("Unsupported color type {} in '{}'",
-                img.color(), path.display()));
-        }
         Err(e)  => {
-            return Err(format!("Could not load '{}': {}", path.display(), e));
+            return Err(Decode(source.to_string(), e.to_string()));
         }
     })
 }
 
-/// A 256x256 image that stores colors.
+// Rejects tile sizes that would break the packing math.
+fn check_unit_size(unit_width: u32, unit_height: u32) -> Result<(), TextureError> {
+    if unit_width == 0 || unit_height == 0 {
+        return Err(ZeroTileSize(unit_width, unit_height));
+    }
+    Ok(())
+}
+
+/// Halves the size of an image by averaging blocks of 2x2 pixels.
+fn downsample(img: &ImageBuf<Rgba<u8>>) -> ImageBuf<Rgba<u8>> {
+    let (w, h) = img.dimensions();
+    ImageBuf::from_fn(cmp::max(w / 2, 1), cmp::max(h / 2, 1), |x, y| {
+        let mut sum = [0u32, ..4];
+        for &(dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)].iter() {
+            let sx = cmp::min(x * 2 + dx, w - 1);
+            let sy = cmp::min(y * 2 + dy, h - 1);
+            let (r, g, b, a) = img.get_pixel(sx, sy).channels();
+            sum[0] += r as u32;
+            sum[1] += g as u32;
+            sum[2] += b as u32;
+            sum[3] += a as u32;
+        }
+        Rgba((sum[0] / 4) as u8, (sum[1] / 4) as u8, (sum[2] / 4) as u8, (sum[3] / 4) as u8)
+    })
+}
+
+/// Grows an image, keeping the old pixels in the top-left corner.
+fn grow(img: &mut ImageBuf<Rgba<u8>>, width: u32, height: u32, fill: Rgba<u8>) {
+    let (w, h) = img.dimensions();
+    let old = mem::replace(img, ImageBuf::from_pixel(width, height, fill));
+    blit(img, (0, 0), &old, [0, 0, w, h]);
+}
+
+/// Copies a rectangle `[x, y, w, h]` of an image into another image, row by row.
+fn blit(
+    dest: &mut ImageBuf<Rgba<u8>>,
+    pos: (u32, u32),
+    src: &ImageBuf<Rgba<u8>>,
+    rect: [u32, ..4]
+) {
+    let (dx, dy) = pos;
+    let [sx, sy, w, h] = rect;
+    let (dest_width, _) = dest.dimensions();
+    let (src_width, _) = src.dimensions();
+    let (dest, src) = (dest.mutable_pixelbuf(), src.pixelbuf());
+    for row in range(0, h) {
+        let d = ((dy + row) * dest_width + dx) as uint;
+        let s = ((sy + row) * src_width + sx) as uint;
+        dest.slice_mut(d, d + w as uint).clone_from_slice(src.slice(s, s + w as uint));
+    }
+}
+
+// Fills transparent pixels of a rectangle `[x, y, w, h]` of an image
+// with the average color of the filled pixels around them within the rectangle,
+// growing by one ring of pixels per iteration and keeping their alpha.
+fn dilate(img: &mut ImageBuf<Rgba<u8>>, rect: [u32, ..4], iterations: u32) {
+    let [x0, y0, w, h] = rect;
+    let index = |x: u32, y: u32| (y * w + x) as uint;
+    let mut filled = Vec::from_fn((w * h) as uint, |i| {
+        img.get_pixel(x0 + i as u32 % w, y0 + i as u32 / w).alpha() > 0
+    });
+
+    for _ in range(0, iterations) {
+        let mut grown = vec![];
+        for y in range(0, h) {
+            for x in range(0, w) {
+                if filled[index(x, y)] {
+                    continue;
+                }
+                let (mut sum, mut count) = ([0u32, ..3], 0u32);
+                for dy in range(-1i32, 2) {
+                    for dx in range(-1i32, 2) {
+                        let (nx, ny) = (x as i32 + dx, y as i32 + dy);
+                        if nx < 0 || ny < 0 || nx >= w as i32 || ny >= h as i32
+                            || !filled[index(nx as u32, ny as u32)] {
+                            continue;
+                        }
+                        let (r, g, b, _) = img.get_pixel(x0 + nx as u32, y0 + ny as u32).channels();
+                        sum[0] += r as u32;
+                        sum[1] += g as u32;
+                        sum[2] += b as u32;
+                        count += 1;
+                    }
+                }
+                if count > 0 {
+                    let (_, _, _, a) = img.get_pixel(x0 + x, y0 + y).channels();
+                    let color = Rgba((sum[0] / count) as u8, (sum[1] / count) as u8,
+                        (sum[2] / count) as u8, a);
+                    grown.push((x, y, color));
+                }
+            }
+        }
+        if grown.is_empty() {
+            break;
+        }
+        for &(x, y, color) in grown.iter() {
+            img.put_pixel(x0 + x, y0 + y, color);
+            filled[index(x, y)] = true;
+        }
+    }
+}
+
+/// How lookup coordinates of a `ColorMap` map to the image.
+#[deriving(Clone, PartialEq, Eq, Show)]
+pub enum ColorMapLayout {
+    /// The y coordinate is scaled by x, so only the lower triangle is used,
+    /// as in Minecraft-style grass and foliage color maps.
+    Triangle,
+    /// The coordinates cover the whole image,
+    /// as in temperature by humidity color maps.
+    Square
+}
+
+/// A corner of an image.
+#[deriving(Clone, PartialEq, Eq, Show)]
+pub enum Corner {
+    /// The top left corner.
+    TopLeft,
+    /// The top right corner.
+    TopRight,
+    /// The bottom left corner.
+    BottomLeft,
+    /// The bottom right corner.
+    BottomRight
+}
+
+/// An image that stores colors, such as a 256x256 biome color map.
+///
+/// By default, lookups use the triangle layout with the origin in the bottom-right corner.
 pub struct ColorMap {
-    image: ImageBuf<Rgba<u8>>
+    image: ImageBuf<Rgba<u8>>,
+    layout: ColorMapLayout,
+    // Corner of the image at lookup coordinates (0.0, 0.0).
+    origin: Corner
 }
 
 impl ColorMap {
     /// Creates a new `ColorMap` from path.
-    pub fn from_path(path: &Path) -> Result<ColorMap, String> {
+    pub fn from_path(path: &Path) -> Result<ColorMap, TextureError> {
         let img = try!(load_rgba8(path));
+        match ColorMap::from_image(img) {
+            Err(EmptyImage(None)) => Err(EmptyImage(Some(path.display().to_string()))),
+            result => result
+        }
+    }
+
+    /// Creates a new `ColorMap` from encoded bytes, for example from `include_bytes!`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<ColorMap, TextureError> {
+        let img = try!(load_rgba8_from_memory(bytes, "<memory>"));
+        ColorMap::from_image(img)
+    }
 
+    /// Creates a new `ColorMap` from an image.
+    pub fn from_image(img: ImageBuf<Rgba<u8>>) -> Result<ColorMap, TextureError> {
         match img.dimensions() {
-            (256, 256) => Ok(ColorMap {image: img}),
-            (w, h) => Err(format!("ColorMap expected 256x256, found {}x{} in '{}'",
-                                  w, h, path.display()))
+            (0, _) | (_, 0) => Err(EmptyImage(None)),
+            _ => Ok(ColorMap {image: img, layout: Triangle, origin: BottomRight})
         }
     }
 
+    /// Sets the layout of the color map and the corner of the image
+    /// at lookup coordinates `(0.0, 0.0)`.
+    pub fn with_layout(self, layout: ColorMapLayout, origin: Corner) -> ColorMap {
+        ColorMap { layout: layout, origin: origin, ..self }
+    }
+
+    /// Returns the layout of the color map.
+    pub fn layout(&self) -> ColorMapLayout {
+        self.layout
+    }
+
+    /// Returns the corner of the image at lookup coordinates `(0.0, 0.0)`.
+    pub fn origin(&self) -> Corner {
+        self.origin
+    }
+
     /// Gets RGB color from the color map.
     pub fn get(&self, x: f32, y: f32) -> [u8, ..3] {
-        // Clamp to [0.0, 1.0].
-        let x = x.max(0.0).min(1.0);
-        let y = y.max(0.0).min(1.0);
+        let (x, y) = self.texel(x, y);
+        let (r, g, b, _) = self.image.get_pixel(x as u32, y as u32).channels();
+        [r, g, b]
+    }
+
+    /// Gets RGB color from the color map,
+    /// interpolating between the four nearest pixels.
+    pub fn get_bilinear(&self, x: f32, y: f32) -> [u8, ..3] {
+        let (x, y) = self.texel(x, y);
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as u32, y0 as u32);
+        let (w, h) = self.image.dimensions();
+        let (x1, y1) = (cmp::min(x0 + 1, w - 1), cmp::min(y0 + 1, h - 1));
 
-        // Scale y from [0.0, 1.0] to [0.0, x], forming a triangle.
-        let y = x * y;
+        let channels = |x: u32, y: u32| {
+            let (r, g, b, _) = self.image.get_pixel(x, y).channels();
+            [r as f32, g as f32, b as f32]
+        };
+        let (c00, c10) = (channels(x0, y0), channels(x1, y0));
+        let (c01, c11) = (channels(x0, y1), channels(x1, y1));
 
-        // Origin is in the bottom-right corner.
-        let x = ((1.0 - x) * 255.0) as u8;
-        let y = ((1.0 - y) * 255.0) as u8;
+        let mut color = [0u8, ..3];
+        for i in range(0u, 3) {
+            let top = c00[i] + (c10[i] - c00[i]) * fx;
+            let bottom = c01[i] + (c11[i] - c01[i]) * fx;
+            color[i] = (top + (bottom - top) * fy + 0.5) as u8;
+        }
+        color
+    }
 
+    /// Gets RGB color of a biome from its temperature and rainfall, both in [0.0, 1.0].
+    ///
+    /// Values are clamped, then rainfall is scaled by temperature,
+    /// so hot biomes can be wet or dry, while cold biomes are always dry.
+    /// The image is addressed at `(temperature, rainfall * temperature)`,
+    /// which is the triangle used by biome color maps, regardless of the layout.
+    pub fn biome_color(&self, temperature: f32, rainfall: f32) -> [u8, ..3] {
+        let temperature = temperature.max(0.0).min(1.0);
+        let rainfall = rainfall.max(0.0).min(1.0) * temperature;
+        let (x, y) = self.corner_texel(temperature, rainfall);
         let (r, g, b, _) = self.image.get_pixel(x as u32, y as u32).channels();
         [r, g, b]
     }
+
+    /// Precomputes `get` on a grid of `resolution` by `resolution` lookups,
+    /// such that hot loops can use `sample_lut` instead of `get`.
+    /// With `n = resolution - 1`, the entry at index `j * resolution + i` is `get(i / n, j / n)`,
+    /// so the layout remap is already applied.
+    /// Will fail if the resolution is zero.
+    pub fn bake_lut(&self, resolution: u32) -> Vec<[u8, ..3]> {
+        assert!(resolution > 0, "lookup table resolution must be positive");
+
+        let step = 1.0 / cmp::max(resolution - 1, 1) as f32;
+        let mut lut = Vec::with_capacity((resolution * resolution) as uint);
+        for j in range(0, resolution) {
+            for i in range(0, resolution) {
+                lut.push(self.get(i as f32 * step, j as f32 * step));
+            }
+        }
+        lut
+    }
+
+    /// Uploads the color map to a texture, for sampling it in shaders.
+    ///
+    /// The image is uploaded with its first row at `t = 0`.
+    /// To match `get_bilinear` with linear filtering, a shader must apply
+    /// the same layout remap and address pixel centers.
+    /// For the default triangle layout with the origin in the bottom-right corner:
+    ///
+    /// ```ignore
+    /// uniform sampler2D colormap;
+    ///
+    /// vec3 colormap_get(float x, float y) {
+    ///     x = clamp(x, 0.0, 1.0);
+    ///     y = clamp(y, 0.0, 1.0) * x;
+    ///     vec2 size = vec2(textureSize(colormap, 0));
+    ///     vec2 texel = (1.0 - vec2(x, y)) * (size - 1.0) + 0.5;
+    ///     return texture(colormap, texel / size).rgb;
+    /// }
+    /// ```
+    pub fn to_texture(&self, d: &Display) -> Texture2d {
+        Texture2d::new(d, self.image.clone())
+    }
+
+    /// Uploads the color map to an sRGB texture,
+    /// which is converted to linear colors when sampled, like `AtlasBuilder::complete_srgb`.
+    /// Lookups work the same as with `to_texture`.
+    pub fn to_srgb_texture(&self, d: &Display) -> SrgbTexture2d {
+        SrgbTexture2d::new(d, self.image.clone())
+    }
+
+    // Computes the pixel coordinates of a lookup.
+    fn texel(&self, x: f32, y: f32) -> (f32, f32) {
+        lookup_texel(self.layout, self.origin, self.image.dimensions(), x, y)
+    }
+
+    // Computes the pixel coordinates of clamped lookup coordinates, relative to the origin.
+    fn corner_texel(&self, x: f32, y: f32) -> (f32, f32) {
+        origin_texel(self.origin, self.image.dimensions(), x, y)
+    }
+}
+
+// Computes the pixel coordinates of a lookup in a color map of `(w, h)` pixels,
+// in the range [0.0, w - 1] and [0.0, h - 1].
+fn lookup_texel(
+    layout: ColorMapLayout,
+    origin: Corner,
+    dimensions: (u32, u32),
+    x: f32,
+    y: f32
+) -> (f32, f32) {
+    // Clamp to [0.0, 1.0].
+    let x = x.max(0.0).min(1.0);
+    let y = y.max(0.0).min(1.0);
+
+    // Scale y from [0.0, 1.0] to [0.0, x], forming a triangle.
+    let y = match layout {
+        Triangle => x * y,
+        Square => y
+    };
+    origin_texel(origin, dimensions, x, y)
+}
+
+// Computes the pixel coordinates of clamped lookup coordinates, relative to the origin.
+fn origin_texel(origin: Corner, dimensions: (u32, u32), x: f32, y: f32) -> (f32, f32) {
+    let (w, h) = dimensions;
+    let (w, h) = ((w - 1) as f32, (h - 1) as f32);
+    match origin {
+        TopLeft => (x * w, y * h),
+        TopRight => ((1.0 - x) * w, y * h),
+        BottomLeft => (x * w, (1.0 - y) * h),
+        BottomRight => ((1.0 - x) * w, (1.0 - y) * h)
+    }
+}
+
+/// A color map with floating point colors, for example for HDR tints or lightmaps,
+/// with the same lookups as `ColorMap` but without quantizing colors to 8 bits.
+#[deriving(Clone)]
+pub struct ColorMapF32 {
+    width: u32,
+    height: u32,
+    // RGB colors, row by row.
+    pixels: Vec<[f32, ..3]>,
+    layout: ColorMapLayout,
+    // Corner of the image at lookup coordinates (0.0, 0.0).
+    origin: Corner
+}
+
+impl ColorMapF32 {
+    /// Creates a new `ColorMapF32` from RGB colors, row by row,
+    /// for example decoded from an HDR image.
+    /// Returns a `WrongDimensions` error if the number of colors is not `width * height`,
+    /// with the number of colors as the found width.
+    pub fn from_pixels(
+        width: u32,
+        height: u32,
+        pixels: Vec<[f32, ..3]>
+    ) -> Result<ColorMapF32, TextureError> {
+        if width == 0 || height == 0 {
+            return Err(EmptyImage(None));
+        }
+        if pixels.len() != (width * height) as uint {
+            let found = (pixels.len() as u32, 1);
+            return Err(WrongDimensions("pixels".to_string(), (width, height), found));
+        }
+        Ok(ColorMapF32 {
+            width: width,
+            height: height,
+            pixels: pixels,
+            layout: Triangle,
+            origin: BottomRight
+        })
+    }
+
+    /// Converts a `ColorMap` to floating point colors in the range `[0.0, 1.0]`,
+    /// keeping its layout and origin.
+    pub fn from_color_map(map: &ColorMap) -> ColorMapF32 {
+        let (width, height) = map.image.dimensions();
+        let mut pixels = Vec::with_capacity((width * height) as uint);
+        for y in range(0, height) {
+            for x in range(0, width) {
+                let (r, g, b, _) = map.image.get_pixel(x, y).channels();
+                pixels.push([r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0]);
+            }
+        }
+        ColorMapF32 {
+            width: width,
+            height: height,
+            pixels: pixels,
+            layout: map.layout,
+            origin: map.origin
+        }
+    }
+
+    /// Sets the layout of the color map and the corner of the image
+    /// at lookup coordinates `(0.0, 0.0)`, see `ColorMap::with_layout`.
+    pub fn with_layout(self, layout: ColorMapLayout, origin: Corner) -> ColorMapF32 {
+        ColorMapF32 { layout: layout, origin: origin, ..self }
+    }
+
+    /// Returns the size of the color map, in pixels.
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Gets RGB color from the color map, like `ColorMap::get`.
+    pub fn get(&self, x: f32, y: f32) -> [f32, ..3] {
+        let (x, y) = lookup_texel(self.layout, self.origin, self.dimensions(), x, y);
+        self.pixel(x as u32, y as u32)
+    }
+
+    /// Gets RGB color from the color map,
+    /// interpolating between the four nearest pixels, like `ColorMap::get_bilinear`.
+    pub fn get_bilinear(&self, x: f32, y: f32) -> [f32, ..3] {
+        let (x, y) = lookup_texel(self.layout, self.origin, self.dimensions(), x, y);
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as u32, y0 as u32);
+        let (x1, y1) = (cmp::min(x0 + 1, self.width - 1), cmp::min(y0 + 1, self.height - 1));
+
+        let (c00, c10) = (self.pixel(x0, y0), self.pixel(x1, y0));
+        let (c01, c11) = (self.pixel(x0, y1), self.pixel(x1, y1));
+
+        let mut color = [0.0, ..3];
+        for i in range(0u, 3) {
+            let top = c00[i] + (c10[i] - c00[i]) * fx;
+            let bottom = c01[i] + (c11[i] - c01[i]) * fx;
+            color[i] = top + (bottom - top) * fy;
+        }
+        color
+    }
+
+    /// Gets RGB color of a biome from its temperature and rainfall,
+    /// like `ColorMap::biome_color`.
+    pub fn biome_color(&self, temperature: f32, rainfall: f32) -> [f32, ..3] {
+        let temperature = temperature.max(0.0).min(1.0);
+        let rainfall = rainfall.max(0.0).min(1.0) * temperature;
+        let (x, y) = origin_texel(self.origin, self.dimensions(), temperature, rainfall);
+        self.pixel(x as u32, y as u32)
+    }
+
+    // Gets the color of a pixel.
+    fn pixel(&self, x: u32, y: u32) -> [f32, ..3] {
+        self.pixels[(y * self.width + x) as uint]
+    }
+}
+
+/// Checks that an image contains one or more frames of the tile size.
+fn check_tile(
+    img: &ImageBuf<Rgba<u8>>,
+    unit_width: u32,
+    unit_height: u32,
+    source: &str
+) -> Result<(), TextureError> {
+    let (iw, ih) = img.dimensions();
+    if iw != unit_width || ih == 0 || (ih % unit_height) != 0 {
+        return Err(TileSizeMismatch(source.to_string(), (unit_width, unit_height), (iw, ih)));
+    }
+    Ok(())
+}
+
+/// Generates a magenta and black checkerboard of 2x2 cells, for missing tiles.
+fn placeholder(width: u32, height: u32) -> ImageBuf<Rgba<u8>> {
+    let (cw, ch) = (cmp::max(width / 2, 1), cmp::max(height / 2, 1));
+    ImageBuf::from_fn(width, height, |x, y| {
+        if (x / cw + y / ch) % 2 == 0 {
+            Rgba(255, 0, 255, 255)
+        } else {
+            Rgba(0, 0, 0, 255)
+        }
+    })
+}
+
+/// Hashes the pixels of a tile sized region of an image.
+fn tile_hash(
+    img: &ImageBuf<Rgba<u8>>,
+    offset: (u32, u32),
+    unit_width: u32,
+    unit_height: u32
+) -> u64 {
+    let (ox, oy) = offset;
+    let mut state = SipState::new();
+    for y in range(oy, oy + unit_height) {
+        for x in range(ox, ox + unit_width) {
+            img.get_pixel(x, y).channels().hash(&mut state);
+        }
+    }
+    state.result()
+}
+
+/// Removes the cached values of rectangles overlapping a rectangle.
+fn remove_overlapping<V>(cache: &mut HashMap<(u32, u32, u32, u32), V>, rect: [u32, ..4]) {
+    let [x, y, w, h] = rect;
+    let stale: Vec<(u32, u32, u32, u32)> = cache.keys()
+        .filter(|&&(rx, ry, rw, rh)| rx < x + w && x < rx + rw && ry < y + h && y < ry + rh)
+        .map(|rect| *rect)
+        .collect();
+    for rect in stale.iter() {
+        cache.remove(rect);
+    }
+}
+
+/// Transparency of a tile, used to decide how to render it.
+#[deriving(Clone, PartialEq, Eq, Show)]
+pub enum AlphaClass {
+    /// Every pixel is fully opaque.
+    Opaque,
+    /// Every pixel is either fully opaque or fully transparent,
+    /// which can be drawn with alpha testing.
+    Cutout,
+    /// Some pixels are partially transparent,
+    /// which must be drawn with blending after opaque geometry.
+    Translucent
+}
+
+/// A rectangle of an atlas, in pixels.
+#[deriving(Clone, PartialEq, Eq, Show)]
+pub struct TextureRegion {
+    /// Left edge of the region.
+    pub x: u32,
+    /// Top edge of the region.
+    pub y: u32,
+    /// Width of the region.
+    pub w: u32,
+    /// Height of the region.
+    pub h: u32,
+    /// Whether the region is mirrored horizontally.
+    pub flipped_h: bool,
+    /// Whether the region is mirrored vertically.
+    pub flipped_v: bool,
+    /// Counter-clockwise quarter turns of the region, applied after mirroring.
+    pub quarter_turns: u8
+}
+
+impl TextureRegion {
+    /// Creates a new `TextureRegion` without flipping or rotation.
+    pub fn new(x: u32, y: u32, w: u32, h: u32) -> TextureRegion {
+        TextureRegion {
+            x: x,
+            y: y,
+            w: w,
+            h: h,
+            flipped_h: false,
+            flipped_v: false,
+            quarter_turns: 0
+        }
+    }
+
+    /// Computes the normalized texture coordinates of the corners of the region,
+    /// in the same order as `cube::TEX_COORDS`:
+    /// bottom right, bottom left, top left, top right.
+    pub fn uv(&self, atlas_width: u32, atlas_height: u32) -> [[f32, ..2], ..4] {
+        let (w, h) = (atlas_width as f32, atlas_height as f32);
+        let (mut u0, mut u1) = (self.x as f32 / w, (self.x + self.w) as f32 / w);
+        let (mut v0, mut v1) = (self.y as f32 / h, (self.y + self.h) as f32 / h);
+        if self.flipped_h { mem::swap(&mut u0, &mut u1); }
+        if self.flipped_v { mem::swap(&mut v0, &mut v1); }
+
+        // Image rows go down, so the bottom of the quad uses the bottom of the region.
+        let corners = [[u1, v1], [u0, v1], [u0, v0], [u1, v0]];
+
+        // The corners go clockwise, so taking the next corner turns counter-clockwise.
+        let mut uv = corners;
+        for i in range(0u, 4) {
+            uv[i] = corners[(i + self.quarter_turns as uint) % 4];
+        }
+        uv
+    }
+
+    /// Returns the region mirrored horizontally.
+    pub fn flip_h(&self) -> TextureRegion {
+        TextureRegion { flipped_h: !self.flipped_h, ..*self }
+    }
+
+    /// Returns the region mirrored vertically.
+    pub fn flip_v(&self) -> TextureRegion {
+        TextureRegion { flipped_v: !self.flipped_v, ..*self }
+    }
+
+    /// Returns the region turned counter-clockwise by quarter turns,
+    /// for example to break up repetition with a rotation picked per block.
+    /// Only the texture coordinates change, not the atlas.
+    pub fn rotated(&self, quarter_turns: u8) -> TextureRegion {
+        // Both counts are reduced first, such that the sum can not overflow.
+        TextureRegion { quarter_turns: (self.quarter_turns % 4 + quarter_turns % 4) % 4, ..*self }
+    }
+
+    /// Returns the region mirrored horizontally if `h` is `true`,
+    /// and vertically if `v` is `true`.
+    pub fn mirrored(&self, h: bool, v: bool) -> TextureRegion {
+        TextureRegion {
+            flipped_h: self.flipped_h != h,
+            flipped_v: self.flipped_v != v,
+            ..*self
+        }
+    }
+
+    /// Returns a rectangle within the region, relative to its top-left corner,
+    /// for example a single frame of an animation strip.
+    /// Keeps the flipping and rotation of the region.
+    pub fn sub(&self, x: u32, y: u32, w: u32, h: u32) -> TextureRegion {
+        assert!(x + w <= self.w && y + h <= self.h, "Sub region out of bounds");
+        TextureRegion { x: self.x + x, y: self.y + y, w: w, h: h, ..*self }
+    }
+}
+
+/// Statistics about the alpha values of a tile, see `AtlasBuilder::tile_stats`.
+#[deriving(Clone, PartialEq, Show)]
+pub struct TileStats {
+    /// The lowest alpha value.
+    pub min_alpha: u8,
+    /// The highest alpha value.
+    pub max_alpha: u8,
+    /// The fraction of fully transparent pixels, from `0.0` to `1.0`.
+    pub transparent_fraction: f32
+}
+
+/// How the image buffer of an `AtlasBuilder` grows when it is full.
+/// Tiles keep their positions when it grows.
+#[deriving(Clone, PartialEq, Eq, Show)]
+pub enum Growth {
+    /// Doubles both the width and the height, keeping the atlas square.
+    /// Tiles fill square shells around the top-left corner.
+    Doubling,
+    /// Doubles only the shorter side, or the width if both are equal,
+    /// which needs up to half the memory of `Doubling` for some tile counts.
+    /// Tiles fill the new slots row by row.
+    Alternating
 }
 
+/// The default maximum width and height of an atlas, in pixels.
+pub const DEFAULT_MAX_SIZE: u32 = 4096;
+
 /// Builds an atlas of textures.
+///
+/// Tiles are placed in slots of `unit_width + 2 * padding` by
+/// `unit_height + 2 * padding` pixels.
+/// When the atlas is full, the image buffer doubles in both dimensions,
+/// which keeps the slot grid aligned, so positions of loaded tiles stay valid.
+///
+/// A partially built atlas can be cloned to build several variants from the same tiles,
+/// without loading them again.
+/// Cloning duplicates the image buffer.
+#[deriving(Clone)]
 pub struct AtlasBuilder {
     image: ImageBuf<Rgba<u8>>,
     // Base path for loading tiles.
@@ -70,105 +795,2008 @@ pub struct AtlasBuilder {
     // Size of an individual tile.
     unit_width: u32,
     unit_height: u32,
+    // Gutter around each tile, in pixels.
+    padding: u32,
+    // Maximum width and height of the image buffer.
+    max_size: u32,
+    // Color of empty space in the image buffer.
+    clear_color: Rgba<u8>,
+    // Initial width and height of the image buffer, in tiles.
+    initial_tiles: u32,
     // Size of the entirely occupied square, in tiles.
     completed_tiles_size: u32,
     // Position in the current strip.
     position: u32,
+    // How the image buffer grows.
+    growth: Growth,
+    // Free slots in placement order, for `Alternating` growth.
+    free_slots: RingBuf<(u32, u32)>,
     // Position cache for loaded tiles (in pixels).
     tile_positions: HashMap<String, (u32, u32)>,
     // Lowest-alpha cache for rectangles in the atlas.
-    min_alpha_cache: HashMap<(u32, u32, u32, u32), u8>
+    min_alpha_cache: HashMap<(u32, u32, u32, u32), u8>,
+    // Transparency cache for rectangles in the atlas.
+    alpha_class_cache: HashMap<(u32, u32, u32, u32), AlphaClass>,
+    // Opaque bounds cache for rectangles in the atlas.
+    opaque_bounds_cache: HashMap<(u32, u32, u32, u32), Option<[u32, ..4]>>,
+    // Alpha statistics cache for rectangles in the atlas.
+    tile_stats_cache: HashMap<(u32, u32, u32, u32), TileStats>,
+    // Number of extra frames ignored by `load`.
+    ignored_frames: HashMap<String, u32>,
+    // Filter used to resize tiles of the wrong size, if enabled.
+    resize_filter: Option<FilterType>,
+    // Positions of placed tiles by pixel hash, if deduplication is enabled.
+    tile_hashes: Option<HashMap<u64, Vec<(u32, u32)>>>,
+    // Names of tiles replaced by a placeholder, if enabled.
+    missing: Option<Vec<String>>,
+    // Color of transparent pixels in tiles without alpha, if enabled.
+    colorkey: Option<Rgb<u8>>
 }
 
 impl AtlasBuilder {
     /// Creates a new `AtlasBuilder`.
-    pub fn new(path: Path, unit_width: u32, unit_height: u32) -> AtlasBuilder {
+    /// Returns an error if the tile width or height is zero.
+    pub fn new(
+        path: Path,
+        unit_width: u32,
+        unit_height: u32
+    ) -> Result<AtlasBuilder, TextureError> {
+        AtlasBuilder::builder(path, unit_width, unit_height).build()
+    }
+
+    /// Starts configuring a new `AtlasBuilder`, see `AtlasConfig`.
+    pub fn builder(path: Path, unit_width: u32, unit_height: u32) -> AtlasConfig {
+        AtlasConfig { atlas: AtlasBuilder::unallocated(path, unit_width, unit_height) }
+    }
+
+    /// Creates a new `AtlasBuilder` with a gutter of `padding` pixels around each tile,
+    /// see `AtlasConfig::padding`.
+    pub fn with_padding(
+        path: Path,
+        unit_width: u32,
+        unit_height: u32,
+        padding: u32
+    ) -> Result<AtlasBuilder, TextureError> {
+        AtlasBuilder::builder(path, unit_width, unit_height).padding(padding).build()
+    }
+
+    /// Creates a new `AtlasBuilder` that never grows beyond `max_size` pixels
+    /// in width or height, see `AtlasConfig::max_size`.
+    pub fn with_max_size(
+        path: Path,
+        unit_width: u32,
+        unit_height: u32,
+        max_size: u32
+    ) -> Result<AtlasBuilder, TextureError> {
+        AtlasBuilder::builder(path, unit_width, unit_height).max_size(max_size).build()
+    }
+
+    /// Creates a new `AtlasBuilder` that fills empty space with a color,
+    /// see `AtlasConfig::clear_color`.
+    pub fn with_clear_color(
+        path: Path,
+        unit_width: u32,
+        unit_height: u32,
+        color: Rgba<u8>
+    ) -> Result<AtlasBuilder, TextureError> {
+        AtlasBuilder::builder(path, unit_width, unit_height).clear_color(color).build()
+    }
+
+    /// Creates a new `AtlasBuilder` with an image buffer of `tiles` by `tiles` slots,
+    /// see `AtlasConfig::initial_size`.
+    pub fn with_initial_size(
+        path: Path,
+        unit_width: u32,
+        unit_height: u32,
+        tiles: u32
+    ) -> Result<AtlasBuilder, TextureError> {
+        AtlasBuilder::builder(path, unit_width, unit_height).initial_size(tiles).build()
+    }
+
+    /// Creates a new `AtlasBuilder` large enough for `tile_count` tiles,
+    /// see `AtlasConfig::capacity`.
+    pub fn with_capacity(
+        path: Path,
+        unit_width: u32,
+        unit_height: u32,
+        tile_count: u32
+    ) -> Result<AtlasBuilder, TextureError> {
+        AtlasBuilder::builder(path, unit_width, unit_height).capacity(tile_count).build()
+    }
+
+    /// Creates a new `AtlasBuilder` that resizes tiles of the wrong size,
+    /// see `AtlasConfig::resize`.
+    pub fn with_resize(
+        path: Path,
+        unit_width: u32,
+        unit_height: u32,
+        filter: FilterType
+    ) -> Result<AtlasBuilder, TextureError> {
+        AtlasBuilder::builder(path, unit_width, unit_height).resize(filter).build()
+    }
+
+    /// Creates a new `AtlasBuilder` that places identical tiles only once,
+    /// see `AtlasConfig::dedup`.
+    pub fn with_dedup(
+        path: Path,
+        unit_width: u32,
+        unit_height: u32
+    ) -> Result<AtlasBuilder, TextureError> {
+        AtlasBuilder::builder(path, unit_width, unit_height).dedup(true).build()
+    }
+
+    /// Creates a new `AtlasBuilder` that substitutes a placeholder for missing tiles,
+    /// see `AtlasConfig::missing_placeholder`.
+    pub fn with_missing_placeholder(
+        path: Path,
+        unit_width: u32,
+        unit_height: u32
+    ) -> Result<AtlasBuilder, TextureError> {
+        AtlasBuilder::builder(path, unit_width, unit_height).missing_placeholder(true).build()
+    }
+
+    /// Creates a new `AtlasBuilder` that grows its image buffer with a strategy,
+    /// see `AtlasConfig::growth`.
+    pub fn with_growth(
+        path: Path,
+        unit_width: u32,
+        unit_height: u32,
+        growth: Growth
+    ) -> Result<AtlasBuilder, TextureError> {
+        AtlasBuilder::builder(path, unit_width, unit_height).growth(growth).build()
+    }
+
+    /// Creates a new `AtlasBuilder` that makes pixels of a color transparent,
+    /// see `AtlasConfig::colorkey`.
+    pub fn with_colorkey(
+        path: Path,
+        unit_width: u32,
+        unit_height: u32,
+        key: Rgb<u8>
+    ) -> Result<AtlasBuilder, TextureError> {
+        AtlasBuilder::builder(path, unit_width, unit_height).colorkey(key).build()
+    }
+
+    // Creates an `AtlasBuilder` with default settings and an empty image.
+    fn unallocated(path: Path, unit_width: u32, unit_height: u32) -> AtlasBuilder {
         AtlasBuilder {
-            image: ImageBuf::new(unit_width * 4, unit_height * 4),
+            image: ImageBuf::new(0, 0),
             path: path,
             unit_width: unit_width,
             unit_height: unit_height,
+            padding: 0,
+            max_size: DEFAULT_MAX_SIZE,
+            clear_color: Rgba(0, 0, 0, 0),
+            initial_tiles: 4,
             completed_tiles_size: 0,
             position: 0,
+            growth: Doubling,
+            free_slots: RingBuf::new(),
             tile_positions: HashMap::new(),
-            min_alpha_cache: HashMap::new()
+            min_alpha_cache: HashMap::new(),
+            alpha_class_cache: HashMap::new(),
+            opaque_bounds_cache: HashMap::new(),
+            tile_stats_cache: HashMap::new(),
+            ignored_frames: HashMap::new(),
+            resize_filter: None,
+            tile_hashes: None,
+            missing: None,
+            colorkey: None
         }
     }
 
+    // Allocates the initial image buffer.
+    fn allocate(&mut self) {
+        let (sw, sh) = self.slot_size();
+        let tiles = self.initial_tiles;
+        self.image = ImageBuf::from_pixel(sw * tiles, sh * tiles, self.clear_color);
+        self.free_slots.clear();
+        if self.growth == Alternating {
+            for y in range(0, tiles) {
+                for x in range(0, tiles) {
+                    self.free_slots.push_back((x, y));
+                }
+            }
+        }
+    }
+
+    // Checks the size of a tile, resizing it if enabled.
+    fn conform(
+        &self,
+        img: ImageBuf<Rgba<u8>>,
+        source: &str
+    ) -> Result<ImageBuf<Rgba<u8>>, TextureError> {
+        let img = self.apply_colorkey(img);
+        match (check_tile(&img, self.unit_width, self.unit_height, source), self.resize_filter) {
+            (Ok(()), _) => Ok(img),
+            (Err(_), Some(filter)) => {
+                Ok(imageops::resize(&img, self.unit_width, self.unit_height, filter))
+            }
+            (Err(e), None) => Err(e)
+        }
+    }
+
+    // Makes the pixels matching the colorkey transparent, if enabled.
+    fn apply_colorkey(&self, mut img: ImageBuf<Rgba<u8>>) -> ImageBuf<Rgba<u8>> {
+        let Rgb(kr, kg, kb) = match self.colorkey {
+            Some(key) => key,
+            None => return img
+        };
+        for pixel in img.mutable_pixelbuf().iter_mut() {
+            let (r, g, b, _) = pixel.channels();
+            if (r, g, b) == (kr, kg, kb) {
+                *pixel = Rgba(r, g, b, 0);
+            }
+        }
+        img
+    }
+
+    // Substitutes a placeholder for a tile that is missing or can not be decoded, if enabled.
+    // Other errors, such as a wrong tile size, are returned so broken packs are noticed.
+    fn or_placeholder(
+        &mut self,
+        name: &str,
+        result: Result<ImageBuf<Rgba<u8>>, TextureError>
+    ) -> Result<ImageBuf<Rgba<u8>>, TextureError> {
+        match (result, self.missing.is_some()) {
+            (Ok(img), _) => Ok(img),
+            (Err(Io(..)), true) | (Err(Decode(..)), true) => {
+                self.missing.as_mut().unwrap().push(name.to_string());
+                Ok(placeholder(self.unit_width, self.unit_height))
+            }
+            (Err(e), _) => Err(e)
+        }
+    }
+
+    // Size of a tile including its gutter.
+    fn slot_size(&self) -> (u32, u32) {
+        (self.unit_width + 2 * self.padding, self.unit_height + 2 * self.padding)
+    }
+
     /// Loads a file into the texture atlas.
     /// Checks if the file is loaded and returns position within the atlas.
     /// The name should be specified without file extension.
-    /// PNG is the only supported format.
-    pub fn load(&mut self, name: &str) -> (u32, u32) {
+    /// Loads the file with the `png` extension.
+    pub fn load(&mut self, name: &str) -> Result<(u32, u32), TextureError> {
+        self.load_with_extension(name, "png")
+    }
+
+    /// Loads a file into the texture atlas like `load`,
+    /// and returns the region of the tile within the atlas.
+    pub fn load_region(&mut self, name: &str) -> Result<TextureRegion, TextureError> {
+        let (x, y) = try!(self.load(name));
+        Ok(TextureRegion::new(x, y, self.unit_width, self.unit_height))
+    }
+
+    /// Loads a file with a specific extension into the texture atlas.
+    /// Any format supported by the `image` crate can be used, e.g. `jpg`.
+    /// The name is used as the cache key, so it should not include the extension.
+    pub fn load_with_extension(
+        &mut self,
+        name: &str,
+        ext: &str
+    ) -> Result<(u32, u32), TextureError> {
         match self.tile_positions.find_equiv(name) {
-            Some(pos) => return *pos,
+            Some(pos) => return Ok(*pos),
             None => {}
         }
 
-        let mut path = self.path.join(name);
-        path.set_extension("png");
-        let img = load_rgba8(&path).unwrap();
+        let path = self.tile_path(name, ext);
+        let source = path.display().to_string();
+        let img = load_rgba8(&path).and_then(|img| self.conform(img, source.as_slice()));
+        let img = try!(self.or_placeholder(name, img));
 
-        let (iw, ih) = img.dimensions();
-        assert!(iw == self.unit_width);
-        assert!((ih % self.unit_height) == 0);
+        let (_, ih) = img.dimensions();
         if ih > self.unit_height {
-            println!("ignoring {} extra frames in '{}'", (ih / self.unit_height) - 1, name);
+            self.ignored_frames.insert(name.to_string(), (ih / self.unit_height) - 1);
         }
 
-        let (uw, uh) = (self.unit_width, self.unit_height);
-        let (w, h) = self.image.dimensions();
-        let size = self.completed_tiles_size;
+        self.place(name, &img, (0, 0))
+    }
 
-        // Expand the image buffer if necessary.
-        if self.position == 0 && (uw * size >= w || uh * size >= h) {
-            let old = mem::replace(&mut self.image, ImageBuf::new(w * 2, h * 2));
-            let mut dest = SubImage::new(&mut self.image, 0, 0, w, h);
-            for ((_, _, a), (_, _, b)) in dest.pixels_mut().zip(old.pixels()) {
-                *a = b;
+    /// Loads all frames of an animated tile, stacked vertically in the file.
+    /// Each frame is placed as its own tile, named `name#index`,
+    /// and `name` refers to the first frame unless it is already loaded.
+    /// Returns the positions of the frames in order.
+    pub fn load_animated(&mut self, name: &str) -> Result<Vec<(u32, u32)>, TextureError> {
+        if self.tile_positions.contains_key(&format!("{}#0", name)) {
+            let mut frames = vec![];
+            loop {
+                match self.tile_positions.get(&format!("{}#{}", name, frames.len())) {
+                    Some(pos) => frames.push(*pos),
+                    None => return Ok(frames)
+                }
             }
         }
 
-        let (x, y) = if self.position < size {
-            (self.position, size)
-        } else {
-            (size, self.position - size)
-        };
+        let path = self.tile_path(name, "png");
+        let img = try!(load_rgba8(&path));
+        let img = try!(self.conform(img, path.display().to_string().as_slice()));
 
-        self.position += 1;
-        if self.position >= size * 2 + 1 {
-            self.position = 0;
-            self.completed_tiles_size += 1;
+        let (_, ih) = img.dimensions();
+        let mut frames = vec![];
+        for i in range(0, ih / self.unit_height) {
+            let frame = format!("{}#{}", name, i);
+            frames.push(try!(self.place(frame.as_slice(), &img, (0, i * self.unit_height))));
+        }
+        if self.tile_positions.find_equiv(name).is_none() {
+            self.tile_positions.insert(name.to_string(), frames[0]);
         }
+        Ok(frames)
+    }
 
-        let mut dest = SubImage::new(&mut self.image, x * uw, y * uh, uw, uh);
-        for ((_, _, a), (_, _, b)) in dest.pixels_mut().zip(img.pixels()) {
-            *a = b;
+    /// Loads a sprite sheet of `cols` by `rows` tiles,
+    /// placing each cell as its own tile, named `name#index`.
+    /// Returns the positions of the cells in row-major order.
+    /// Returns an error if the sheet is not exactly `cols` by `rows` tiles.
+    pub fn load_sheet(
+        &mut self,
+        name: &str,
+        cols: u32,
+        rows: u32
+    ) -> Result<Vec<(u32, u32)>, TextureError> {
+        if self.tile_positions.contains_key(&format!("{}#0", name)) {
+            let mut cells = vec![];
+            for i in range(0, cols * rows) {
+                match self.tile_positions.get(&format!("{}#{}", name, i)) {
+                    Some(pos) => cells.push(*pos),
+                    None => break
+                }
+            }
+            return Ok(cells);
         }
 
-        *match self.tile_positions.entry(name.to_string()) {
-            Occupied(entry) => entry.into_mut(),
-            Vacant(entry) => entry.set((x * uw, y * uh))
+        let path = self.tile_path(name, "png");
+        let img = self.apply_colorkey(try!(load_rgba8(&path)));
+        let (uw, uh) = (self.unit_width, self.unit_height);
+        let (iw, ih) = img.dimensions();
+        if iw != cols * uw || ih != rows * uh {
+            return Err(SheetDimensions(path.display().to_string(),
+                (cols, rows), (uw, uh), (iw, ih)));
         }
-    }
 
-    /// Finds the minimum alpha value in a given sub texture of the image.
-    pub fn min_alpha(&mut self, rect: [u32, ..4]) -> u8 {
-        let [x, y, w, h] = rect;
-        match self.min_alpha_cache.get(&(x, y, w, h)) {
-            Some(alpha) => return *alpha,
-            None => {}
+        let mut cells = vec![];
+        for row in range(0, rows) {
+            for col in range(0, cols) {
+                let cell = format!("{}#{}", name, row * cols + col);
+                cells.push(try!(self.place(cell.as_slice(), &img, (col * uw, row * uh))));
+            }
         }
+        Ok(cells)
+    }
 
-        let tile = SubImage::new(&mut self.image, x, y, w, h);
-        let min_alpha = tile.pixels().map(|(_, _, p)| p.alpha())
-            .min().unwrap_or(0);
-        self.min_alpha_cache.insert((x, y, w, h), min_alpha);
-        min_alpha
+    /// Returns the number of extra animation frames ignored by `load`, per tile.
+    pub fn ignored_frames(&self) -> &HashMap<String, u32> {
+        &self.ignored_frames
     }
 
-    /// Returns the complete texture atlas as a texture.
-    pub fn complete(self, d: &Display) -> Texture2d {
-        Texture2d::new(d, self.image)
+    // Path of a tile file.
+    fn tile_path(&self, name: &str, ext: &str) -> Path {
+        let mut path = self.path.join(name);
+        path.set_extension(ext);
+        path
     }
-}
\ No newline at end of file
+
+    /// Loads multiple files into the texture atlas and returns their positions.
+    /// Stops at the first tile that fails to load.
+    pub fn load_all<'a, I: Iterator<&'a str>>(
+        &mut self,
+        names: I
+    ) -> Result<HashMap<String, (u32, u32)>, TextureError> {
+        let mut positions = HashMap::new();
+        for name in names {
+            match self.load(name) {
+                Ok(pos) => { positions.insert(name.to_string(), pos); }
+                Err(e) => return Err(Tile(name.to_string(), box e))
+            }
+        }
+        Ok(positions)
+    }
+
+    /// Loads a tile from encoded bytes into the texture atlas,
+    /// for example from `include_bytes!`.
+    /// The name is used as the cache key, so repeated calls do not decode the bytes again.
+    pub fn load_bytes(&mut self, name: &str, bytes: &[u8]) -> Result<(u32, u32), TextureError> {
+        match self.tile_positions.find_equiv(name) {
+            Some(pos) => return Ok(*pos),
+            None => {}
+        }
+
+        let img = load_rgba8_from_memory(bytes, name).and_then(|img| self.conform(img, name));
+        let img = try!(self.or_placeholder(name, img));
+        self.place(name, &img, (0, 0))
+    }
+
+    /// Replaces the pixels of a loaded tile and returns its position within the atlas.
+    /// Cached alpha values overlapping the tile are discarded.
+    /// If the tile is not loaded yet, it is placed like a freshly loaded tile.
+    /// A tile sharing its slot with deduplicated names is placed again,
+    /// so the other names keep their pixels.
+    pub fn replace(
+        &mut self,
+        name: &str,
+        img: ImageBuf<Rgba<u8>>
+    ) -> Result<(u32, u32), TextureError> {
+        let img = try!(self.conform(img, name));
+
+        let pos = match self.tile_positions.find_equiv(name) {
+            Some(pos) => *pos,
+            None => return self.place(name, &img, (0, 0))
+        };
+        let shared = self.tile_positions.iter()
+            .any(|(other, &other_pos)| other_pos == pos && other.as_slice() != name);
+        if shared {
+            self.tile_positions.remove(&name.to_string());
+            return self.place(name, &img, (0, 0));
+        }
+        self.write_tile(pos, &img, (0, 0));
+
+        // Later duplicates must find the new pixels instead of the old ones.
+        let hash = tile_hash(&img, (0, 0), self.unit_width, self.unit_height);
+        match self.tile_hashes.as_mut() {
+            Some(hashes) => {
+                for (_, positions) in hashes.iter_mut() {
+                    positions.retain(|&p| p != pos);
+                }
+                match hashes.entry(hash) {
+                    Occupied(entry) => entry.into_mut().push(pos),
+                    Vacant(entry) => { entry.set(vec![pos]); }
+                }
+            }
+            None => {}
+        }
+
+        let (x, y) = pos;
+        let rect = [x, y, self.unit_width, self.unit_height];
+        remove_overlapping(&mut self.min_alpha_cache, rect);
+        remove_overlapping(&mut self.alpha_class_cache, rect);
+        remove_overlapping(&mut self.opaque_bounds_cache, rect);
+        remove_overlapping(&mut self.tile_stats_cache, rect);
+        Ok(pos)
+    }
+
+    // Places a tile sized region of an image in the next free slot.
+    fn place(
+        &mut self,
+        name: &str,
+        img: &ImageBuf<Rgba<u8>>,
+        offset: (u32, u32)
+    ) -> Result<(u32, u32), TextureError> {
+        let hash = match self.tile_hashes {
+            Some(_) => {
+                let hash = tile_hash(img, offset, self.unit_width, self.unit_height);
+                match self.find_duplicate(img, offset, hash) {
+                    Some(pos) => {
+                        self.tile_positions.insert(name.to_string(), pos);
+                        return Ok(pos);
+                    }
+                    None => Some(hash)
+                }
+            }
+            None => None
+        };
+
+        let (x, y) = match self.growth {
+            Doubling => try!(self.next_shell_slot(name)),
+            Alternating => try!(self.next_free_slot(name))
+        };
+        let (sw, sh) = self.slot_size();
+        let pos = (x * sw + self.padding, y * sh + self.padding);
+        self.write_tile(pos, img, offset);
+        match (self.tile_hashes.as_mut(), hash) {
+            (Some(hashes), Some(hash)) => match hashes.entry(hash) {
+                Occupied(entry) => entry.into_mut().push(pos),
+                Vacant(entry) => { entry.set(vec![pos]); }
+            },
+            _ => {}
+        }
+
+        Ok(*match self.tile_positions.entry(name.to_string()) {
+            Occupied(entry) => entry.into_mut(),
+            Vacant(entry) => entry.set(pos)
+        })
+    }
+
+    // Takes the next slot of the square shells filled by `Doubling`,
+    // doubling the image buffer when the next shell does not fit.
+    fn next_shell_slot(&mut self, name: &str) -> Result<(u32, u32), TextureError> {
+        let (sw, sh) = self.slot_size();
+        let (w, h) = self.image.dimensions();
+        let size = self.completed_tiles_size;
+
+        // Expand the image buffer if necessary.
+        if self.position == 0 && (sw * size >= w || sh * size >= h) {
+            if w * 2 > self.max_size || h * 2 > self.max_size {
+                return Err(AtlasFull(name.to_string(), self.max_size));
+            }
+            grow(&mut self.image, w * 2, h * 2, self.clear_color);
+            self.invalidate_alpha_cache();
+        }
+
+        let slot = if self.position < size {
+            (self.position, size)
+        } else {
+            (size, self.position - size)
+        };
+
+        self.position += 1;
+        if self.position >= size * 2 + 1 {
+            self.position = 0;
+            self.completed_tiles_size += 1;
+        }
+        Ok(slot)
+    }
+
+    // Takes the next free slot for `Alternating`,
+    // doubling the shorter side of the image buffer when there is none.
+    fn next_free_slot(&mut self, name: &str) -> Result<(u32, u32), TextureError> {
+        if self.free_slots.is_empty() {
+            let (sw, sh) = self.slot_size();
+            let (w, h) = self.image.dimensions();
+            let (cols, rows) = (w / sw, h / sh);
+            if w <= h {
+                if w * 2 > self.max_size {
+                    return Err(AtlasFull(name.to_string(), self.max_size));
+                }
+                grow(&mut self.image, w * 2, h, self.clear_color);
+                for y in range(0, rows) {
+                    for x in range(cols, cols * 2) {
+                        self.free_slots.push_back((x, y));
+                    }
+                }
+            } else {
+                if h * 2 > self.max_size {
+                    return Err(AtlasFull(name.to_string(), self.max_size));
+                }
+                grow(&mut self.image, w, h * 2, self.clear_color);
+                for y in range(rows, rows * 2) {
+                    for x in range(0, cols) {
+                        self.free_slots.push_back((x, y));
+                    }
+                }
+            }
+            self.invalidate_alpha_cache();
+        }
+        Ok(self.free_slots.pop_front().unwrap())
+    }
+
+    // Finds a placed tile with the same pixels as a tile sized region of an image.
+    // Pixels are compared, since tiles can be replaced after placing them.
+    fn find_duplicate(
+        &self,
+        img: &ImageBuf<Rgba<u8>>,
+        offset: (u32, u32),
+        hash: u64
+    ) -> Option<(u32, u32)> {
+        let candidates = match self.tile_hashes.as_ref().and_then(|hashes| hashes.get(&hash)) {
+            Some(candidates) => candidates,
+            None => return None
+        };
+        let (ox, oy) = offset;
+        for &(x, y) in candidates.iter() {
+            let mut equal = true;
+            for ty in range(0, self.unit_height) {
+                for tx in range(0, self.unit_width) {
+                    if self.image.get_pixel(x + tx, y + ty).channels()
+                        != img.get_pixel(ox + tx, oy + ty).channels() {
+                        equal = false;
+                        break;
+                    }
+                }
+                if !equal { break; }
+            }
+            if equal {
+                return Some((x, y));
+            }
+        }
+        None
+    }
+
+    // Writes a tile sized region of an image at a tile position,
+    // clamping the edge pixels into the surrounding gutter.
+    fn write_tile(&mut self, pos: (u32, u32), img: &ImageBuf<Rgba<u8>>, offset: (u32, u32)) {
+        let (x, y) = pos;
+        let (ox, oy) = offset;
+        let (uw, uh, p) = (self.unit_width, self.unit_height, self.padding);
+        blit(&mut self.image, pos, img, [ox, oy, uw, uh]);
+        if p == 0 {
+            return;
+        }
+
+        let (w, _) = self.image.dimensions();
+        let pixels = self.image.mutable_pixelbuf();
+        let index = |x: u32, y: u32| (y * w + x) as uint;
+        for gy in range(0, uh + 2 * p) {
+            // Rows of the top and bottom gutters repeat the edge rows of the tile.
+            let sy = y + cmp::min(cmp::max(gy, p) - p, uh - 1);
+            let dy = y + gy - p;
+            let (left, right) = (pixels[index(x, sy)], pixels[index(x + uw - 1, sy)]);
+            for gx in range(0, p) {
+                pixels[index(x - p + gx, dy)] = left;
+                pixels[index(x + uw + gx, dy)] = right;
+            }
+            if dy != sy {
+                let (start, end) = (index(x, sy), index(x + uw, sy));
+                let row = pixels.slice(start, end).to_vec();
+                pixels.slice_mut(index(x, dy), index(x + uw, dy)).clone_from_slice(row.as_slice());
+            }
+        }
+    }
+
+    /// Finds the minimum alpha value in a given sub texture of the image.
+    pub fn min_alpha(&mut self, rect: [u32, ..4]) -> u8 {
+        let [x, y, w, h] = rect;
+        match self.min_alpha_cache.get(&(x, y, w, h)) {
+            Some(alpha) => return *alpha,
+            None => {}
+        }
+
+        let tile = SubImage::new(&mut self.image, x, y, w, h);
+        let min_alpha = tile.pixels().map(|(_, _, p)| p.alpha())
+            .min().unwrap_or(0);
+        self.min_alpha_cache.insert((x, y, w, h), min_alpha);
+        min_alpha
+    }
+
+    /// Finds the minimum alpha value of a loaded tile,
+    /// or `None` if the tile is not loaded.
+    pub fn min_alpha_of(&mut self, name: &str) -> Option<u8> {
+        let (x, y) = match self.tile_positions.find_equiv(name) {
+            Some(pos) => *pos,
+            None => return None
+        };
+        Some(self.min_alpha([x, y, self.unit_width, self.unit_height]))
+    }
+
+    /// Classifies the transparency of a loaded tile,
+    /// or returns `None` if the tile is not loaded.
+    pub fn alpha_class(&mut self, name: &str) -> Option<AlphaClass> {
+        let (x, y) = match self.tile_positions.find_equiv(name) {
+            Some(pos) => *pos,
+            None => return None
+        };
+        let key = (x, y, self.unit_width, self.unit_height);
+        match self.alpha_class_cache.get(&key) {
+            Some(class) => return Some(*class),
+            None => {}
+        }
+
+        let tile = SubImage::new(&mut self.image, x, y, self.unit_width, self.unit_height);
+        let mut class = Opaque;
+        for (_, _, p) in tile.pixels() {
+            match p.alpha() {
+                255 => {}
+                0 => class = Cutout,
+                _ => { class = Translucent; break; }
+            }
+        }
+        self.alpha_class_cache.insert(key, class);
+        Some(class)
+    }
+
+    /// Computes statistics about the alpha values of a loaded tile,
+    /// for example to find tiles that are accidentally translucent or empty,
+    /// or returns `None` if the tile is not loaded.
+    pub fn tile_stats(&mut self, name: &str) -> Option<TileStats> {
+        let (x, y) = match self.tile_positions.find_equiv(name) {
+            Some(pos) => *pos,
+            None => return None
+        };
+        let key = (x, y, self.unit_width, self.unit_height);
+        match self.tile_stats_cache.get(&key) {
+            Some(stats) => return Some(stats.clone()),
+            None => {}
+        }
+
+        let tile = SubImage::new(&mut self.image, x, y, self.unit_width, self.unit_height);
+        let (mut min_alpha, mut max_alpha, mut transparent) = (255u8, 0u8, 0u32);
+        for (_, _, p) in tile.pixels() {
+            let alpha = p.alpha();
+            min_alpha = cmp::min(min_alpha, alpha);
+            max_alpha = cmp::max(max_alpha, alpha);
+            if alpha == 0 {
+                transparent += 1;
+            }
+        }
+        let stats = TileStats {
+            min_alpha: min_alpha,
+            max_alpha: max_alpha,
+            transparent_fraction: transparent as f32 / (self.unit_width * self.unit_height) as f32
+        };
+        self.min_alpha_cache.insert(key, min_alpha);
+        self.tile_stats_cache.insert(key, stats.clone());
+        Some(stats)
+    }
+
+    /// Finds the smallest rectangle `[x, y, w, h]` of a loaded tile,
+    /// relative to the tile, containing every pixel that is not fully transparent,
+    /// for example to fit a billboard quad to a sprite.
+    /// Returns `None` if the tile is not loaded or fully transparent.
+    pub fn opaque_bounds(&mut self, name: &str) -> Option<[u32, ..4]> {
+        let (x, y) = match self.tile_positions.find_equiv(name) {
+            Some(pos) => *pos,
+            None => return None
+        };
+        let key = (x, y, self.unit_width, self.unit_height);
+        match self.opaque_bounds_cache.get(&key) {
+            Some(bounds) => return *bounds,
+            None => {}
+        }
+
+        let tile = SubImage::new(&mut self.image, x, y, self.unit_width, self.unit_height);
+        let mut bounds: Option<(u32, u32, u32, u32)> = None;
+        for (px, py, p) in tile.pixels() {
+            if p.alpha() == 0 {
+                continue;
+            }
+            bounds = Some(match bounds {
+                Some((x0, y0, x1, y1)) => {
+                    (cmp::min(x0, px), cmp::min(y0, py), cmp::max(x1, px), cmp::max(y1, py))
+                }
+                None => (px, py, px, py)
+            });
+        }
+        let bounds = bounds.map(|(x0, y0, x1, y1)| [x0, y0, x1 - x0 + 1, y1 - y0 + 1]);
+        self.opaque_bounds_cache.insert(key, bounds);
+        bounds
+    }
+
+    /// Discards all cached alpha values, such that they are computed again from the pixels.
+    /// This can be used after modifying tiles outside of `replace`, e.g. when hot-reloading.
+    pub fn clear_caches(&mut self) {
+        self.invalidate_alpha_cache();
+    }
+
+    // Discards cached alpha values, which are keyed by rectangle,
+    // whenever tiles could move within the image buffer.
+    fn invalidate_alpha_cache(&mut self) {
+        self.min_alpha_cache.clear();
+        self.alpha_class_cache.clear();
+        self.opaque_bounds_cache.clear();
+        self.tile_stats_cache.clear();
+    }
+
+    /// Computes normalized texture coordinates `[u0, v0, u1, v1]` of every loaded tile,
+    /// relative to the current size of the atlas.
+    /// Should be called after all tiles are loaded, since loading more may grow the atlas.
+    pub fn uv_rects(&self) -> HashMap<String, [f32, ..4]> {
+        let (w, h) = self.image.dimensions();
+        let (w, h) = (w as f32, h as f32);
+        let (uw, uh) = (self.unit_width as f32, self.unit_height as f32);
+        self.tile_positions.iter().map(|(name, &(x, y))| {
+            let (x, y) = (x as f32, y as f32);
+            (name.clone(), [x / w, y / h, (x + uw) / w, (y + uh) / h])
+        }).collect()
+    }
+
+    /// Iterates through the names of all loaded tiles, in arbitrary order.
+    pub fn tile_names(&self) -> TileNames {
+        TileNames { keys: self.tile_positions.keys() }
+    }
+
+    /// Returns the names of the loaded tiles overlapping a rectangle `[x, y, w, h]`
+    /// of the atlas, in pixels, sorted by name.
+    /// The gutter around tiles is not part of them.
+    pub fn tiles_in_rect(&self, rect: [u32, ..4]) -> Vec<&str> {
+        let [x, y, w, h] = rect;
+        let (uw, uh) = (self.unit_width, self.unit_height);
+        let mut names: Vec<&str> = self.tile_positions.iter()
+            .filter(|&(_, &(tx, ty))| tx < x + w && x < tx + uw && ty < y + h && y < ty + uh)
+            .map(|(name, _)| name.as_slice())
+            .collect();
+        names.sort();
+        names
+    }
+
+    /// Returns `true` if a tile is loaded.
+    pub fn contains(&self, name: &str) -> bool {
+        self.tile_positions.find_equiv(name).is_some()
+    }
+
+    /// Returns the current size of the image buffer, in pixels.
+    /// Loading more tiles may grow the atlas,
+    /// so this is the size of the completed texture only if no tiles are loaded afterwards.
+    pub fn dimensions(&self) -> (u32, u32) {
+        self.image.dimensions()
+    }
+
+    /// Returns the positions of all loaded tiles (in pixels).
+    pub fn positions(&self) -> &HashMap<String, (u32, u32)> {
+        &self.tile_positions
+    }
+
+    /// Returns the number of loaded tiles, for example to show loading progress.
+    /// Names sharing a slot, such as deduplicated tiles, are counted separately.
+    pub fn tile_count(&self) -> uint {
+        self.tile_positions.len()
+    }
+
+    /// Returns the fraction of the slots of the current image buffer that are used,
+    /// from `0.0` to `1.0`.
+    /// The image buffer grows when it is full, so this drops after growing.
+    pub fn occupancy(&self) -> f32 {
+        let (sw, sh) = self.slot_size();
+        let (w, h) = self.image.dimensions();
+        let capacity = (w / sw) * (h / sh);
+        if capacity == 0 {
+            return 0.0;
+        }
+        let used = match self.growth {
+            Doubling => {
+                let size = self.completed_tiles_size;
+                size * size + self.position
+            }
+            Alternating => capacity - self.free_slots.len() as u32
+        };
+        used as f32 / capacity as f32
+    }
+
+    /// Writes the layout of the atlas to a text file.
+    /// Each line contains the name, position and size of a tile, `name x y w h`,
+    /// sorted by name.
+    /// Returns an `InvalidInput` error without writing anything
+    /// if a tile name contains whitespace, since it could not be read back.
+    pub fn save_manifest(&self, path: &Path) -> IoResult<()> {
+        let mut tiles: Vec<(&String, &(u32, u32))> = self.tile_positions.iter().collect();
+        tiles.sort_by(|&(a, _), &(b, _)| a.cmp(b));
+        for &(name, _) in tiles.iter() {
+            if name.as_slice().chars().any(|c| c.is_whitespace()) {
+                return Err(IoError {
+                    kind: InvalidInput,
+                    desc: "tile names in a manifest must not contain whitespace",
+                    detail: Some(name.clone())
+                });
+            }
+        }
+
+        let mut file = try!(File::create(path));
+        for &(name, &(x, y)) in tiles.iter() {
+            try!(writeln!(file, "{} {} {} {} {}", name, x, y, self.unit_width, self.unit_height));
+        }
+        Ok(())
+    }
+
+    /// Writes the image of the atlas to a PNG file and its layout to a manifest,
+    /// see `save_manifest`, such that it can be loaded with `Atlas::load_prebuilt`
+    /// instead of loading and packing the tiles again.
+    pub fn save(&self, png_path: &Path, manifest_path: &Path) -> IoResult<()> {
+        let mut file = try!(File::create(png_path));
+        match image::ImageRgba8(self.image.clone()).save(&mut file, image::PNG) {
+            Ok(()) => {}
+            Err(e) => return Err(IoError {
+                kind: OtherIoError,
+                desc: "could not encode atlas",
+                detail: Some(e.to_string())
+            })
+        }
+        self.save_manifest(manifest_path)
+    }
+
+    /// Fills the transparent pixels around the opaque pixels of each tile
+    /// with the color of their neighbors, growing by one ring of pixels per iteration,
+    /// such that linear filtering and mipmaps do not darken the edges of cutout tiles.
+    /// Transparent pixels stay transparent, and colors do not cross into other tiles.
+    /// Call this before `save` for prebuilt atlases to be dilated as well,
+    /// or load them with `Atlas::load_prebuilt_dilated`.
+    pub fn dilate_edges(&mut self, iterations: u32) {
+        let mut slots: Vec<(u32, u32)> = self.tile_positions.values().map(|pos| *pos).collect();
+        slots.sort();
+        slots.dedup();
+
+        let (sw, sh) = self.slot_size();
+        for &(x, y) in slots.iter() {
+            let rect = [x - self.padding, y - self.padding, sw, sh];
+            dilate(&mut self.image, rect, iterations);
+        }
+
+        // The colors changed, so the hashes of deduplicated tiles must be computed again.
+        if self.tile_hashes.is_some() {
+            let mut hashes = HashMap::new();
+            for &pos in slots.iter() {
+                match hashes.entry(tile_hash(&self.image, pos, self.unit_width, self.unit_height)) {
+                    Occupied(entry) => entry.into_mut().push(pos),
+                    Vacant(entry) => { entry.set(vec![pos]); }
+                }
+            }
+            self.tile_hashes = Some(hashes);
+        }
+    }
+
+    /// Places the loaded tiles again in order of their names, regardless of load order,
+    /// such that the same set of tiles always produces the same atlas and positions,
+    /// for example to compare the atlas against a reference image.
+    /// Names sharing a position, such as deduplicated tiles, keep sharing it.
+    /// This is opt-in, since positions change and differ from load order packing.
+    pub fn repack_sorted(&mut self) -> Result<(), TextureError> {
+        let mut tiles: Vec<(String, (u32, u32))> = self.tile_positions.iter()
+            .map(|(name, pos)| (name.clone(), *pos))
+            .collect();
+        tiles.sort();
+
+        let old = mem::replace(&mut self.image, ImageBuf::new(0, 0));
+        self.allocate();
+        self.completed_tiles_size = 0;
+        self.position = 0;
+        self.tile_positions.clear();
+        self.invalidate_alpha_cache();
+        if self.tile_hashes.is_some() {
+            self.tile_hashes = Some(HashMap::new());
+        }
+
+        // New positions of the old positions, for names sharing a tile.
+        let mut moved = HashMap::new();
+        for (name, pos) in tiles.into_iter() {
+            let existing = moved.get(&pos).map(|pos| *pos);
+            let new_pos = match existing {
+                Some(new_pos) => new_pos,
+                None => {
+                    let new_pos = try!(self.place(name.as_slice(), &old, pos));
+                    moved.insert(pos, new_pos);
+                    new_pos
+                }
+            };
+            self.tile_positions.insert(name, new_pos);
+        }
+        Ok(())
+    }
+
+    /// Returns the complete texture atlas as a texture,
+    /// after placing the tiles in order of their names, see `repack_sorted`.
+    pub fn complete_sorted(mut self, d: &Display) -> Result<Texture2d, TextureError> {
+        try!(self.repack_sorted());
+        Ok(self.complete(d))
+    }
+
+    /// Borrows the image buffer of the atlas,
+    /// for example to upload it with another texture format or compression.
+    pub fn image(&self) -> &ImageBuf<Rgba<u8>> {
+        &self.image
+    }
+
+    /// Returns the image buffer of the atlas, without uploading it.
+    /// Loading tiles does not need a display,
+    /// so an atlas can be built on another thread
+    /// and its image uploaded on the render thread with `upload`.
+    pub fn into_image(self) -> ImageBuf<Rgba<u8>> {
+        self.image
+    }
+
+    /// Returns the complete texture atlas as a texture.
+    pub fn complete(self, d: &Display) -> Texture2d {
+        Texture2d::new(d, self.image)
+    }
+
+    /// Returns the complete texture atlas as an sRGB texture,
+    /// which is converted to linear colors when sampled,
+    /// such that lighting in shaders is computed in linear space.
+    /// Tints from a `ColorMap` are sRGB colors as well,
+    /// and should be converted to linear space before multiplying them with the atlas,
+    /// see `ColorMap::to_srgb_texture`.
+    pub fn complete_srgb(self, d: &Display) -> SrgbTexture2d {
+        SrgbTexture2d::new(d, self.image)
+    }
+
+    /// Returns the complete texture atlas, together with the layout of its tiles.
+    pub fn complete_atlas(self, d: &Display) -> Atlas {
+        let (width, height) = self.image.dimensions();
+        Atlas {
+            texture: Texture2d::new(d, self.image),
+            width: width,
+            height: height,
+            unit_width: self.unit_width,
+            unit_height: self.unit_height,
+            positions: self.tile_positions
+        }
+    }
+
+    /// Returns the complete texture atlas as a texture,
+    /// together with the names of tiles replaced by a placeholder,
+    /// see `AtlasConfig::missing_placeholder`.
+    pub fn complete_with_missing(mut self, d: &Display) -> (Texture2d, Vec<String>) {
+        let missing = self.missing.take().unwrap_or(vec![]);
+        (self.complete(d), missing)
+    }
+
+    /// Returns the complete texture atlas as a texture,
+    /// with the magnify filter and wrap function to sample it with.
+    /// For pixel art, use nearest filtering and clamp to edge.
+    pub fn complete_with(
+        self,
+        d: &Display,
+        filter: MagnifySamplerFilter,
+        wrap: SamplerWrapFunction
+    ) -> AtlasTexture {
+        AtlasTexture::new(self.complete(d)).with_filter(filter).with_wrap(wrap)
+    }
+
+    /// Returns the complete texture atlas as a texture with a full mipmap chain.
+    /// Lower levels are generated by box-downsampling the atlas,
+    /// so the tile gutters keep colors from bleeding into neighbor tiles.
+    /// Without padding, mipmap bleeding can still occur at tile edges.
+    pub fn complete_with_mipmaps(self, d: &Display) -> Texture2d {
+        let mut levels = vec![];
+        let mut level = downsample(&self.image);
+        loop {
+            let next = match level.dimensions() {
+                (1, 1) => None,
+                _ => Some(downsample(&level))
+            };
+            levels.push(level);
+            match next {
+                Some(next) => level = next,
+                None => break
+            }
+        }
+
+        let texture = Texture2d::new(d, self.image);
+        for (i, img) in levels.into_iter().enumerate() {
+            let (w, h) = img.dimensions();
+            let rect = Rect { left: 0, bottom: 0, width: w, height: h };
+            texture.mipmap(i as u32 + 1).unwrap().write(rect, img);
+        }
+        texture
+    }
+
+    /// Returns the complete texture atlas as a texture,
+    /// together with the normalized texture coordinates of every tile.
+    pub fn complete_with_uvs(self, d: &Display) -> (Texture2d, HashMap<String, [f32, ..4]>) {
+        let uvs = self.uv_rects();
+        (self.complete(d), uvs)
+    }
+}
+
+/// Configures a new `AtlasBuilder`, see `AtlasBuilder::builder`.
+///
+/// ```ignore
+/// let atlas = AtlasBuilder::builder(Path::new("assets/blocks"), 16, 16)
+///     .padding(2)
+///     .dedup(true)
+///     .build()
+///     .unwrap();
+/// ```
+pub struct AtlasConfig {
+    // The atlas being configured, without an image buffer.
+    atlas: AtlasBuilder
+}
+
+impl AtlasConfig {
+    /// Sets a gutter of `padding` pixels around each tile.
+    /// The edge pixels of each tile are extended into the gutter,
+    /// such that linear filtering and mipmaps do not pick up colors of neighbor tiles.
+    pub fn padding(mut self, padding: u32) -> AtlasConfig {
+        self.atlas.padding = padding;
+        self
+    }
+
+    /// Sets the maximum width and height of the atlas, in pixels.
+    /// Loading a tile that does not fit returns an error.
+    /// Defaults to `DEFAULT_MAX_SIZE`.
+    pub fn max_size(mut self, max_size: u32) -> AtlasConfig {
+        self.atlas.max_size = max_size;
+        self
+    }
+
+    /// Sets the color of empty space, which is transparent black by default.
+    /// This can be used to make unpacked areas visible while debugging.
+    pub fn clear_color(mut self, color: Rgba<u8>) -> AtlasConfig {
+        self.atlas.clear_color = color;
+        self
+    }
+
+    /// Starts with an image buffer of `tiles` by `tiles` slots, instead of 4 by 4.
+    /// `tiles` is rounded up to the next power of two,
+    /// such that the slot grid stays consistent when the atlas doubles.
+    pub fn initial_size(mut self, tiles: u32) -> AtlasConfig {
+        self.atlas.initial_tiles = cmp::max(tiles, 1).next_power_of_two();
+        self
+    }
+
+    /// Starts with an image buffer large enough for `tile_count` tiles,
+    /// such that loading them does not grow the image buffer.
+    /// The image buffer still grows if more tiles are loaded.
+    /// The image buffer never starts larger than `max_size`,
+    /// so fewer tiles fit before loading returns `AtlasFull` if the capacity exceeds it.
+    pub fn capacity(mut self, tile_count: u32) -> AtlasConfig {
+        let mut side = 1;
+        while side * side < tile_count {
+            side += 1;
+        }
+        self.atlas.initial_tiles = cmp::max(side.next_power_of_two(), self.atlas.initial_tiles);
+        self
+    }
+
+    /// Resizes tiles of the wrong size to `unit_width` by `unit_height` pixels,
+    /// instead of returning an error.
+    /// This is useful when mixing texture packs of different resolutions.
+    /// Resizing is lossy, keeps only a single frame of animated tiles,
+    /// and is disabled by default.
+    pub fn resize(mut self, filter: FilterType) -> AtlasConfig {
+        self.atlas.resize_filter = Some(filter);
+        self
+    }
+
+    /// Places identical tiles only once, which is disabled by default.
+    /// Loading a tile with the same pixels as a placed tile returns the existing position,
+    /// so both names share the same slot, and replacing one of them moves it to its own slot.
+    pub fn dedup(mut self, dedup: bool) -> AtlasConfig {
+        self.atlas.tile_hashes = if dedup { Some(HashMap::new()) } else { None };
+        self
+    }
+
+    /// Substitutes a magenta and black checkerboard for tiles that are missing
+    /// or can not be decoded, instead of returning an error, which is disabled by default.
+    /// The placeholder gets a valid position, so the game keeps running
+    /// while assets are being fixed.
+    /// The names of missing tiles are returned by `AtlasBuilder::complete_with_missing`.
+    /// Tiles of the wrong size still return an error.
+    pub fn missing_placeholder(mut self, placeholder: bool) -> AtlasConfig {
+        self.atlas.missing = if placeholder { Some(vec![]) } else { None };
+        self
+    }
+
+    /// Makes the pixels of loaded tiles with the color `key` fully transparent,
+    /// for example magenta in old tiles without an alpha channel,
+    /// which is disabled by default.
+    /// Such tiles are then classified as `Cutout` by `AtlasBuilder::alpha_class`.
+    /// The color of the pixels is kept, see `AtlasBuilder::dilate_edges` to hide it
+    /// when filtering.
+    pub fn colorkey(mut self, key: Rgb<u8>) -> AtlasConfig {
+        self.atlas.colorkey = Some(key);
+        self
+    }
+
+    /// Sets how the image buffer grows when it is full, which is `Doubling` by default.
+    pub fn growth(mut self, growth: Growth) -> AtlasConfig {
+        self.atlas.growth = growth;
+        self
+    }
+
+    /// Creates the `AtlasBuilder`.
+    /// Returns an error if the tile width or height is zero.
+    pub fn build(self) -> Result<AtlasBuilder, TextureError> {
+        let mut atlas = self.atlas;
+        try!(check_unit_size(atlas.unit_width, atlas.unit_height));
+
+        // Shrink the initial image buffer to the maximum size, halving keeps it a power of two.
+        let (sw, sh) = atlas.slot_size();
+        while atlas.initial_tiles > 1 && cmp::max(sw, sh) * atlas.initial_tiles > atlas.max_size {
+            atlas.initial_tiles /= 2;
+        }
+        atlas.allocate();
+        Ok(atlas)
+    }
+}
+
+/// Dilates each tile of a prebuilt atlas within its own rectangle.
+fn dilate_tiles(
+    img: &mut ImageBuf<Rgba<u8>>,
+    positions: &HashMap<String, (u32, u32)>,
+    unit_size: (u32, u32),
+    iterations: u32
+) {
+    let (uw, uh) = unit_size;
+    let mut slots: Vec<(u32, u32)> = positions.values().map(|pos| *pos).collect();
+    slots.sort();
+    slots.dedup();
+    for &(x, y) in slots.iter() {
+        dilate(img, [x, y, uw, uh], iterations);
+    }
+}
+
+/// Reads a manifest written by `AtlasBuilder::save_manifest`,
+/// returning the positions of the tiles and the tile size.
+/// A manifest without tiles is an error, since the tile size is unknown.
+fn read_manifest(
+    path: &Path
+) -> Result<(HashMap<String, (u32, u32)>, (u32, u32)), TextureError> {
+    let source = path.display().to_string();
+    let text = match File::open(path).read_to_string() {
+        Ok(text) => text,
+        Err(e) => return Err(Io(source, e))
+    };
+
+    let mut unit_size = None;
+    let mut positions = HashMap::new();
+    for (i, line) in text.as_slice().lines().enumerate() {
+        let words: Vec<&str> = line.words().collect();
+        if words.is_empty() {
+            continue;
+        }
+        let numbers: Vec<Option<u32>> = words.slice_from(1).iter()
+            .map(|word| from_str(*word))
+            .collect();
+        let (x, y, w, h) = match numbers.as_slice() {
+            [Some(x), Some(y), Some(w), Some(h)] => (x, y, w, h),
+            _ => {
+                let e = format!("expected `name x y w h` on line {}", i + 1);
+                return Err(Manifest(source, e));
+            }
+        };
+        match unit_size {
+            Some(size) if size != (w, h) => {
+                return Err(Manifest(source, format!("tile size differs on line {}", i + 1)));
+            }
+            _ => unit_size = Some((w, h))
+        }
+        positions.insert(words[0].to_string(), (x, y));
+    }
+    match unit_size {
+        Some(size) => Ok((positions, size)),
+        None => Err(Manifest(source, "no tiles in manifest".to_string()))
+    }
+}
+
+/// A complete texture atlas, together with the layout of its tiles.
+pub struct Atlas {
+    /// The texture of the atlas.
+    pub texture: Texture2d,
+    /// Width of the atlas, in pixels.
+    pub width: u32,
+    /// Height of the atlas, in pixels.
+    pub height: u32,
+    /// Width of a tile, in pixels.
+    pub unit_width: u32,
+    /// Height of a tile, in pixels.
+    pub unit_height: u32,
+    /// Positions of the tiles, in pixels.
+    pub positions: HashMap<String, (u32, u32)>
+}
+
+impl Atlas {
+    /// Loads an atlas saved with `AtlasBuilder::save`,
+    /// without loading the tiles it was built from.
+    pub fn load_prebuilt(png: &Path, manifest: &Path, d: &Display) -> Result<Atlas, TextureError> {
+        Atlas::load_prebuilt_dilated(png, manifest, 0, d)
+    }
+
+    /// Loads an atlas saved with `AtlasBuilder::save` like `load_prebuilt`,
+    /// dilating the edges of its tiles before uploading, see `AtlasBuilder::dilate_edges`.
+    /// The manifest does not record padding, so colors only grow within each tile.
+    pub fn load_prebuilt_dilated(
+        png: &Path,
+        manifest: &Path,
+        iterations: u32,
+        d: &Display
+    ) -> Result<Atlas, TextureError> {
+        let (positions, (unit_width, unit_height)) = try!(read_manifest(manifest));
+        let mut img = try!(load_rgba8(png));
+        if iterations > 0 {
+            dilate_tiles(&mut img, &positions, (unit_width, unit_height), iterations);
+        }
+        let (width, height) = img.dimensions();
+        Ok(Atlas {
+            texture: Texture2d::new(d, img),
+            width: width,
+            height: height,
+            unit_width: unit_width,
+            unit_height: unit_height,
+            positions: positions
+        })
+    }
+
+    /// Returns the region of a tile, or `None` if the tile is not in the atlas.
+    pub fn region(&self, name: &str) -> Option<TextureRegion> {
+        self.positions.find_equiv(name).map(|&(x, y)| {
+            TextureRegion::new(x, y, self.unit_width, self.unit_height)
+        })
+    }
+
+    /// Computes the normalized texture coordinates of the corners of a tile,
+    /// in the same order as `cube::TEX_COORDS`,
+    /// or `None` if the tile is not in the atlas.
+    pub fn uv(&self, name: &str) -> Option<[[f32, ..2], ..4]> {
+        self.region(name).map(|region| region.uv(self.width, self.height))
+    }
+
+    /// Computes the normalized texture coordinates `[u0, v0, u1, v1]` of a tile,
+    /// shrunk inward by `texels` on every side,
+    /// or `None` if the tile is not in the atlas.
+    ///
+    /// Linear filtering samples up to half a texel beyond the coordinates,
+    /// so an inset of `0.5` keeps neighbor tiles from bleeding into the edges.
+    /// The tradeoff is that the edges of the tile are slightly cropped,
+    /// which is visible on small tiles with pixel art borders.
+    /// Padding with `AtlasConfig::padding` avoids bleeding without cropping.
+    pub fn uv_rect_inset(&self, name: &str, texels: f32) -> Option<[f32, ..4]> {
+        self.positions.find_equiv(name).map(|&(x, y)| {
+            let (w, h) = (self.width as f32, self.height as f32);
+            let (x, y) = (x as f32, y as f32);
+            let (uw, uh) = (self.unit_width as f32, self.unit_height as f32);
+            [
+                (x + texels) / w,
+                (y + texels) / h,
+                (x + uw - texels) / w,
+                (y + uh - texels) / h
+            ]
+        })
+    }
+}
+
+/// Iterates through the names of the tiles loaded in an `AtlasBuilder`.
+pub struct TileNames<'a> {
+    keys: Keys<'a, String, (u32, u32)>
+}
+
+impl<'a> Iterator<&'a str> for TileNames<'a> {
+    fn next(&mut self) -> Option<&'a str> {
+        self.keys.next().map(|name| name.as_slice())
+    }
+}
+
+/// A complete texture atlas, together with the sampler settings to draw it with.
+pub struct AtlasTexture {
+    /// The texture of the atlas.
+    pub texture: Texture2d,
+    /// The sampler settings, which default to the settings of an unsampled texture.
+    pub behavior: SamplerBehavior
+}
+
+impl AtlasTexture {
+    /// Creates a new `AtlasTexture` with default sampler settings.
+    pub fn new(texture: Texture2d) -> AtlasTexture {
+        AtlasTexture {
+            texture: texture,
+            behavior: Default::default()
+        }
+    }
+
+    /// Sets the filter used when the atlas is magnified.
+    pub fn with_filter(self, filter: MagnifySamplerFilter) -> AtlasTexture {
+        let behavior = SamplerBehavior { magnify_filter: filter, ..self.behavior };
+        AtlasTexture { behavior: behavior, ..self }
+    }
+
+    /// Sets the filter used when the atlas is minified.
+    pub fn with_minify_filter(self, filter: MinifySamplerFilter) -> AtlasTexture {
+        let behavior = SamplerBehavior { minify_filter: filter, ..self.behavior };
+        AtlasTexture { behavior: behavior, ..self }
+    }
+
+    /// Sets the wrap function of all texture coordinates.
+    pub fn with_wrap(self, wrap: SamplerWrapFunction) -> AtlasTexture {
+        let behavior = SamplerBehavior { wrap_function: (wrap, wrap, wrap), ..self.behavior };
+        AtlasTexture { behavior: behavior, ..self }
+    }
+
+    /// Enables anisotropic filtering, which keeps surfaces seen at grazing angles sharp.
+    /// The value is clamped to the maximum supported by the display,
+    /// and anisotropic filtering is disabled if it is not supported.
+    ///
+    /// This only has an effect with a mipmapped atlas, see `complete_with_mipmaps`.
+    /// Anisotropic filtering reads further along the surface than trilinear filtering,
+    /// so tiles need a wider gutter, see `AtlasBuilder::with_padding`.
+    pub fn with_max_anisotropy(self, d: &Display, max_anisotropy: u16) -> AtlasTexture {
+        let supported = d.get_max_anisotropy_support().unwrap_or(1);
+        let max_anisotropy = cmp::max(cmp::min(max_anisotropy, supported), 1);
+        let behavior = SamplerBehavior { max_anisotropy: max_anisotropy, ..self.behavior };
+        AtlasTexture { behavior: behavior, ..self }
+    }
+
+    /// Returns the atlas with its sampler settings, to be used as a uniform value.
+    pub fn sampled(&self) -> Sampler<Texture2d> {
+        Sampler(&self.texture, self.behavior)
+    }
+}
+
+/// Packs images of different sizes into an atlas.
+///
+/// Uses a skyline algorithm: the bottom edge of the packed images is tracked
+/// as a list of horizontal segments, and each image is placed at the topmost
+/// position where it fits.
+/// When an image does not fit, the image buffer doubles in both dimensions.
+pub struct AtlasPacker {
+    image: ImageBuf<Rgba<u8>>,
+    // Maximum width and height of the image buffer.
+    max_size: u32,
+    // Segments `(x, y, width)` of the skyline, from left to right.
+    skyline: Vec<(u32, u32, u32)>,
+    // Rectangle cache for packed images (in pixels).
+    rects: HashMap<String, [u32, ..4]>
+}
+
+impl AtlasPacker {
+    /// Creates a new `AtlasPacker` with an initial size in pixels.
+    /// Sizes below one pixel are raised to one, since a size of zero would never double.
+    pub fn new(width: u32, height: u32) -> AtlasPacker {
+        let (width, height) = (cmp::max(width, 1), cmp::max(height, 1));
+        AtlasPacker {
+            image: ImageBuf::new(width, height),
+            max_size: DEFAULT_MAX_SIZE,
+            skyline: vec![(0, 0, width)],
+            rects: HashMap::new()
+        }
+    }
+
+    /// Packs an image into the atlas and returns its rectangle `[x, y, w, h]`.
+    /// Checks if the name is packed and returns the cached rectangle.
+    pub fn pack(
+        &mut self,
+        name: &str,
+        img: &ImageBuf<Rgba<u8>>
+    ) -> Result<[u32, ..4], TextureError> {
+        match self.rects.find_equiv(name) {
+            Some(rect) => return Ok(*rect),
+            None => {}
+        }
+
+        let (iw, ih) = img.dimensions();
+        let mut found = self.find_position(iw, ih);
+        while found.is_none() {
+            let (w, h) = self.image.dimensions();
+            if w * 2 > self.max_size || h * 2 > self.max_size {
+                return Err(AtlasFull(name.to_string(), self.max_size));
+            }
+            grow(&mut self.image, w * 2, h * 2, Rgba(0, 0, 0, 0));
+            self.skyline.push((w, 0, w));
+            found = self.find_position(iw, ih);
+        }
+        let (i, x, y) = found.unwrap();
+
+        blit(&mut self.image, (x, y), img, [0, 0, iw, ih]);
+        self.raise_skyline(i, x, y + ih, iw);
+
+        let rect = [x, y, iw, ih];
+        self.rects.insert(name.to_string(), rect);
+        Ok(rect)
+    }
+
+    /// Returns the rectangles of all packed images.
+    pub fn rects(&self) -> &HashMap<String, [u32, ..4]> {
+        &self.rects
+    }
+
+    /// Returns the complete texture atlas as a texture.
+    pub fn complete(self, d: &Display) -> Texture2d {
+        Texture2d::new(d, self.image)
+    }
+
+    // Finds the skyline segment, x and y of the topmost position fitting an image.
+    fn find_position(&self, w: u32, h: u32) -> Option<(uint, u32, u32)> {
+        let (width, height) = self.image.dimensions();
+        let mut best: Option<(uint, u32, u32)> = None;
+        for (i, &(x, _, _)) in self.skyline.iter().enumerate() {
+            if x + w > width { break; }
+
+            // The image rests on the highest segment below it.
+            let mut y = 0;
+            for &(sx, sy, _) in self.skyline.slice_from(i).iter() {
+                if sx >= x + w { break; }
+                y = cmp::max(y, sy);
+            }
+            if y + h > height { continue; }
+
+            match best {
+                Some((_, _, best_y)) if best_y <= y => {}
+                _ => best = Some((i, x, y))
+            }
+        }
+        best
+    }
+
+    // Inserts a segment at index `i`, trimming the segments it covers.
+    fn raise_skyline(&mut self, i: uint, x: u32, y: u32, w: u32) {
+        self.skyline.insert(i, (x, y, w));
+
+        let end = x + w;
+        let j = i + 1;
+        while j < self.skyline.len() {
+            let (sx, sy, sw) = self.skyline[j];
+            if sx >= end { break; }
+            if sx + sw <= end {
+                self.skyline.remove(j);
+            } else {
+                self.skyline[j] = (end, sy, sx + sw - end);
+                break;
+            }
+        }
+
+        // Merge neighbor segments of the same height.
+        let mut k = 0;
+        while k + 1 < self.skyline.len() {
+            let (ax, ay, aw) = self.skyline[k];
+            let (_, by, bw) = self.skyline[k + 1];
+            if ay == by {
+                self.skyline[k] = (ax, ay, aw + bw);
+                self.skyline.remove(k + 1);
+            } else {
+                k += 1;
+            }
+        }
+    }
+}
+
+/// Builds a texture array from tiles of the same size, with one tile per layer.
+///
+/// Unlike an atlas, tiles do not bleed into each other when filtered,
+/// and shaders can repeat a tile by wrapping texture coordinates.
+pub struct ArrayAtlasBuilder {
+    // Base path for loading tiles.
+    path: Path,
+    // Size of an individual tile.
+    unit_width: u32,
+    unit_height: u32,
+    // Loaded tiles, one per layer.
+    layers: Vec<ImageBuf<Rgba<u8>>>,
+    // Layer cache for loaded tiles.
+    tile_layers: HashMap<String, u32>
+}
+
+impl ArrayAtlasBuilder {
+    /// Creates a new `ArrayAtlasBuilder`.
+    /// Returns an error if the tile width or height is zero.
+    pub fn new(
+        path: Path,
+        unit_width: u32,
+        unit_height: u32
+    ) -> Result<ArrayAtlasBuilder, TextureError> {
+        try!(check_unit_size(unit_width, unit_height));
+        Ok(ArrayAtlasBuilder {
+            path: path,
+            unit_width: unit_width,
+            unit_height: unit_height,
+            layers: vec![],
+            tile_layers: HashMap::new()
+        })
+    }
+
+    /// Loads a file into the texture array.
+    /// Checks if the file is loaded and returns its layer.
+    /// The name should be specified without file extension.
+    /// Loads the file with the `png` extension.
+    pub fn load(&mut self, name: &str) -> Result<u32, TextureError> {
+        match self.tile_layers.find_equiv(name) {
+            Some(layer) => return Ok(*layer),
+            None => {}
+        }
+
+        let mut path = self.path.join(name);
+        path.set_extension("png");
+        let img = try!(load_rgba8(&path));
+        try!(check_tile(&img, self.unit_width, self.unit_height,
+            path.display().to_string().as_slice()));
+        Ok(self.push(name, &img))
+    }
+
+    /// Loads a tile from encoded bytes into the texture array.
+    /// The name is used as the cache key, so repeated calls do not decode the bytes again.
+    pub fn load_bytes(&mut self, name: &str, bytes: &[u8]) -> Result<u32, TextureError> {
+        match self.tile_layers.find_equiv(name) {
+            Some(layer) => return Ok(*layer),
+            None => {}
+        }
+
+        let img = try!(load_rgba8_from_memory(bytes, name));
+        try!(check_tile(&img, self.unit_width, self.unit_height, name));
+        Ok(self.push(name, &img))
+    }
+
+    /// Returns the complete texture array.
+    pub fn complete(self, d: &Display) -> Texture2dArray {
+        Texture2dArray::new(d, self.layers)
+    }
+
+    // Adds the first frame of an image as a new layer.
+    fn push(&mut self, name: &str, img: &ImageBuf<Rgba<u8>>) -> u32 {
+        let layer = self.layers.len() as u32;
+        self.layers.push(ImageBuf::from_fn(self.unit_width, self.unit_height, |x, y| {
+            img.get_pixel(x, y)
+        }));
+        self.tile_layers.insert(name.to_string(), layer);
+        layer
+    }
+}
+
+/// Builds several atlases in lockstep, one per channel,
+/// for example an albedo atlas and a normal map atlas.
+///
+/// The tiles of every channel are placed at the same position,
+/// so the positions and texture coordinates of a tile apply to all channels.
+pub struct MultiAtlasBuilder {
+    // Names of the channels, used as file name suffixes.
+    channels: Vec<String>,
+    // One atlas per channel, in the same order as the channel names.
+    atlases: Vec<AtlasBuilder>
+}
+
+impl MultiAtlasBuilder {
+    /// Creates a new `MultiAtlasBuilder` with channels named by `channels`,
+    /// for example `["albedo", "normal"]`.
+    /// Will fail if there are no channels.
+    /// Returns an error if the tile width or height is zero.
+    pub fn new(
+        path: Path,
+        unit_width: u32,
+        unit_height: u32,
+        channels: &[&str]
+    ) -> Result<MultiAtlasBuilder, TextureError> {
+        assert!(!channels.is_empty(), "a multi-atlas needs at least one channel");
+        let mut atlases = vec![];
+        for _ in channels.iter() {
+            atlases.push(try!(AtlasBuilder::new(path.clone(), unit_width, unit_height)));
+        }
+        Ok(MultiAtlasBuilder {
+            channels: channels.iter().map(|channel| channel.to_string()).collect(),
+            atlases: atlases
+        })
+    }
+
+    /// Loads a tile into every channel and returns its position within the atlases.
+    /// Loads `name_channel.png` for each channel, for example `stone_albedo.png`
+    /// and `stone_normal.png`.
+    /// Nothing is placed if the file of any channel fails to load.
+    pub fn load(&mut self, name: &str) -> Result<(u32, u32), TextureError> {
+        match self.atlases[0].tile_positions.find_equiv(name) {
+            Some(pos) => return Ok(*pos),
+            None => {}
+        }
+
+        let mut images = vec![];
+        for (atlas, channel) in self.atlases.iter().zip(self.channels.iter()) {
+            let path = atlas.tile_path(format!("{}_{}", name, channel).as_slice(), "png");
+            let img = try!(load_rgba8(&path));
+            images.push(try!(atlas.conform(img, path.display().to_string().as_slice())));
+        }
+
+        let mut position = None;
+        for (atlas, img) in self.atlases.iter_mut().zip(images.iter()) {
+            let pos = try!(atlas.place(name, img, (0, 0)));
+            assert!(position.map_or(true, |first| first == pos));
+            position = Some(pos);
+        }
+        Ok(position.unwrap())
+    }
+
+    /// Returns the names of the channels.
+    pub fn channels(&self) -> &[String] {
+        self.channels.as_slice()
+    }
+
+    /// Returns the positions of all loaded tiles (in pixels), shared by every channel.
+    pub fn positions(&self) -> &HashMap<String, (u32, u32)> {
+        self.atlases[0].positions()
+    }
+
+    /// Returns the current size of the image buffers, in pixels.
+    pub fn dimensions(&self) -> (u32, u32) {
+        self.atlases[0].dimensions()
+    }
+
+    /// Returns the normalized texture coordinates `[u0, v0, u1, v1]` of each loaded tile,
+    /// shared by every channel, see `AtlasBuilder::uv_rects`.
+    pub fn uv_rects(&self) -> HashMap<String, [f32, ..4]> {
+        self.atlases[0].uv_rects()
+    }
+
+    /// Returns the image buffer of each channel, in the same order as the channel names.
+    pub fn into_images(self) -> Vec<ImageBuf<Rgba<u8>>> {
+        self.atlases.into_iter().map(|atlas| atlas.into_image()).collect()
+    }
+
+    /// Returns the complete texture of each channel, in the same order as the channel names.
+    pub fn complete(self, d: &Display) -> Vec<Texture2d> {
+        self.atlases.into_iter().map(|atlas| atlas.complete(d)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image;
+    use image::{ GenericImage, ImageBuf, ImageLuma8, ImageLumaA8, Luma, LumaA, Pixel, Rgba };
+    use std::cmp;
+    use std::collections::HashMap;
+    use std::io::{ File, InvalidInput, MemWriter, TempDir };
+    use test::Bencher;
+    use super::*;
+    use super::{ dilate_tiles, load_rgba8, read_manifest };
+
+    #[test]
+    fn packer_grows_from_zero_size() {
+        let mut packer = AtlasPacker::new(0, 0);
+        let img = ImageBuf::from_pixel(2, 2, Rgba(255u8, 255, 255, 255));
+        assert_eq!(packer.pack("a", &img), Ok([0, 0, 2, 2]));
+    }
+
+    #[test]
+    fn packer_is_no_larger_than_shells() {
+        let img = ImageBuf::from_pixel(16, 16, Rgba(255u8, 255, 255, 255));
+        let mut packer = AtlasPacker::new(16, 16);
+        let mut atlas = AtlasBuilder::new(Path::new("."), 16, 16).unwrap();
+        for i in range(0u, 100) {
+            let name = format!("tile{}", i);
+            packer.pack(name.as_slice(), &img).unwrap();
+            atlas.replace(name.as_slice(), img.clone()).unwrap();
+        }
+        let (pw, ph) = packer.image.dimensions();
+        let (aw, ah) = atlas.dimensions();
+        assert!(pw * ph <= aw * ah);
+    }
+
+    #[test]
+    fn tiles_are_classified_by_alpha() {
+        let mut atlas = AtlasBuilder::new(Path::new("."), 2, 2).unwrap();
+        atlas.replace("opaque", ImageBuf::from_pixel(2, 2, Rgba(255u8, 0, 0, 255))).unwrap();
+        let mut cutout = ImageBuf::from_pixel(2, 2, Rgba(0u8, 255, 0, 255));
+        cutout.put_pixel(1, 1, Rgba(0, 0, 0, 0));
+        atlas.replace("cutout", cutout).unwrap();
+        atlas.replace("translucent", ImageBuf::from_pixel(2, 2, Rgba(0u8, 0, 255, 128))).unwrap();
+
+        assert_eq!(atlas.alpha_class("opaque"), Some(Opaque));
+        assert_eq!(atlas.min_alpha_of("opaque"), Some(255));
+        assert_eq!(atlas.alpha_class("cutout"), Some(Cutout));
+        assert_eq!(atlas.min_alpha_of("cutout"), Some(0));
+        assert_eq!(atlas.alpha_class("translucent"), Some(Translucent));
+        assert_eq!(atlas.alpha_class("missing"), None);
+    }
+
+    #[test]
+    fn replace_recomputes_min_alpha() {
+        let mut atlas = AtlasBuilder::new(Path::new("."), 2, 2).unwrap();
+        atlas.replace("tile", ImageBuf::from_pixel(2, 2, Rgba(255u8, 255, 255, 255))).unwrap();
+        assert_eq!(atlas.min_alpha_of("tile"), Some(255));
+        atlas.replace("tile", ImageBuf::from_pixel(2, 2, Rgba(255u8, 255, 255, 0))).unwrap();
+        assert_eq!(atlas.min_alpha_of("tile"), Some(0));
+        assert_eq!(atlas.alpha_class("tile"), Some(Cutout));
+    }
+
+    #[test]
+    fn replacing_a_deduplicated_tile_keeps_the_others() {
+        let mut atlas = AtlasBuilder::builder(Path::new("."), 2, 2).dedup(true).build().unwrap();
+        let red = ImageBuf::from_pixel(2, 2, Rgba(255u8, 0, 0, 255));
+        let a = atlas.replace("a", red.clone()).unwrap();
+        let b = atlas.replace("b", red).unwrap();
+        assert_eq!(a, b);
+
+        let clear = ImageBuf::from_pixel(2, 2, Rgba(0u8, 0, 0, 0));
+        let b = atlas.replace("b", clear).unwrap();
+        assert!(a != b);
+        assert_eq!(atlas.positions().get(&"a".to_string()), Some(&a));
+        assert_eq!(atlas.min_alpha_of("a"), Some(255));
+        assert_eq!(atlas.min_alpha_of("b"), Some(0));
+    }
+
+    #[test]
+    fn grayscale_png_loads_as_rgba() {
+        let mut luma = MemWriter::new();
+        ImageLuma8(ImageBuf::from_pixel(2, 2, Luma(128u8))).save(&mut luma, image::PNG).unwrap();
+        let mut luma_alpha = MemWriter::new();
+        ImageLumaA8(ImageBuf::from_pixel(2, 2, LumaA(64u8, 0)))
+            .save(&mut luma_alpha, image::PNG).unwrap();
+
+        let mut atlas = AtlasBuilder::new(Path::new("."), 2, 2).unwrap();
+        let (x, y) = atlas.load_bytes("luma", luma.get_ref()).unwrap();
+        assert_eq!(atlas.image.get_pixel(x, y).channels(), (128, 128, 128, 255));
+        let (x, y) = atlas.load_bytes("luma_alpha", luma_alpha.get_ref()).unwrap();
+        assert_eq!(atlas.image.get_pixel(x, y).channels(), (64, 64, 64, 0));
+    }
+
+    #[test]
+    fn missing_file_is_an_io_error() {
+        let mut atlas = AtlasBuilder::new(Path::new("does-not-exist"), 2, 2).unwrap();
+        assert!(match atlas.load("missing") { Err(Io(..)) => true, _ => false });
+        let garbage = atlas.load_bytes("garbage", b"not a png");
+        assert!(match garbage { Err(Decode(..)) => true, _ => false });
+    }
+
+    #[test]
+    fn manifest_round_trip() {
+        let dir = TempDir::new("glium_voxel").unwrap();
+        let path = dir.path().join("atlas.txt");
+        let mut atlas = AtlasBuilder::new(Path::new("."), 2, 2).unwrap();
+        for name in ["dirt", "grass_top", "stone"].iter() {
+            atlas.replace(*name, ImageBuf::from_pixel(2, 2, Rgba(255u8, 255, 255, 255))).unwrap();
+        }
+        atlas.save_manifest(&path).unwrap();
+        assert_eq!(read_manifest(&path), Ok((atlas.positions().clone(), (2, 2))));
+    }
+
+    #[test]
+    fn saved_atlas_reloads_with_same_tiles() {
+        let dir = TempDir::new("glium_voxel").unwrap();
+        let (png, manifest) = (dir.path().join("atlas.png"), dir.path().join("atlas.txt"));
+        let mut atlas = AtlasBuilder::new(Path::new("."), 2, 2).unwrap();
+        let tiles = [("dirt", Rgba(120u8, 80, 40, 255)), ("grass_top", Rgba(40u8, 200, 40, 255)),
+            ("stone", Rgba(128u8, 128, 128, 128))];
+        for &(name, color) in tiles.iter() {
+            atlas.replace(name, ImageBuf::from_pixel(2, 2, color)).unwrap();
+        }
+        atlas.save(&png, &manifest).unwrap();
+
+        let (positions, (uw, uh)) = read_manifest(&manifest).unwrap();
+        let img = load_rgba8(&png).unwrap();
+        assert_eq!(img.dimensions(), atlas.dimensions());
+        assert_eq!(positions.len(), tiles.len());
+        for &(name, color) in tiles.iter() {
+            let &(x, y) = atlas.positions().find_equiv(name).unwrap();
+            let &(rx, ry) = positions.find_equiv(name).unwrap();
+            assert_eq!(TextureRegion::new(rx, ry, uw, uh), TextureRegion::new(x, y, 2, 2));
+            for (dx, dy) in [(0u32, 0u32), (1, 0), (0, 1), (1, 1)].iter().map(|p| *p) {
+                assert_eq!(img.get_pixel(rx + dx, ry + dy).channels(), color.channels());
+            }
+        }
+    }
+
+    #[test]
+    fn empty_manifest_is_rejected() {
+        let dir = TempDir::new("glium_voxel").unwrap();
+        let path = dir.path().join("atlas.txt");
+        File::create(&path).write_str("\n").unwrap();
+        assert!(match read_manifest(&path) { Err(Manifest(..)) => true, _ => false });
+    }
+
+    #[test]
+    fn manifest_rejects_names_with_spaces() {
+        let dir = TempDir::new("glium_voxel").unwrap();
+        let path = dir.path().join("atlas.txt");
+        let mut atlas = AtlasBuilder::new(Path::new("."), 2, 2).unwrap();
+        atlas.replace("grass top", ImageBuf::from_pixel(2, 2, Rgba(255u8, 255, 255, 255))).unwrap();
+        assert_eq!(atlas.save_manifest(&path).map_err(|e| e.kind), Err(InvalidInput));
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn half_turn_mirrors_both_axes() {
+        let region = TextureRegion::new(16, 0, 16, 16);
+        let half_turn = region.rotated(1).rotated(1);
+        assert_eq!(half_turn, region.rotated(2));
+        assert_eq!(half_turn.uv(64, 32), region.mirrored(true, true).uv(64, 32));
+    }
+
+    #[test]
+    fn large_turn_counts_wrap_around() {
+        let region = TextureRegion::new(16, 0, 16, 16);
+        assert_eq!(region.rotated(255), region.rotated(3));
+        assert_eq!(region.rotated(255).rotated(255), region.rotated(2));
+        assert_eq!(region.rotated(64), region);
+    }
+
+    #[test]
+    fn one_pixel_tiles_get_distinct_positions() {
+        let mut atlas = AtlasBuilder::new(Path::new("."), 1, 1).unwrap();
+        let a = atlas.replace("a", ImageBuf::from_pixel(1, 1, Rgba(255u8, 0, 0, 255))).unwrap();
+        let b = atlas.replace("b", ImageBuf::from_pixel(1, 1, Rgba(0u8, 255, 0, 255))).unwrap();
+        assert!(a != b);
+
+        let (w, h) = atlas.dimensions();
+        let (w, h) = (w as f32, h as f32);
+        let uvs = atlas.uv_rects();
+        let tiles = [("a", a, (255u8, 0u8, 0u8, 255u8)), ("b", b, (0, 255, 0, 255))];
+        for &(name, (x, y), color) in tiles.iter() {
+            let (fx, fy) = (x as f32, y as f32);
+            let rect = [fx / w, fy / h, (fx + 1.0) / w, (fy + 1.0) / h];
+            assert_eq!(uvs.get(&name.to_string()), Some(&rect));
+            assert_eq!(atlas.image.get_pixel(x, y).channels(), color);
+        }
+    }
+
+    #[test]
+    fn zero_tile_size_is_an_error() {
+        assert!(match AtlasBuilder::new(Path::new("."), 0, 16) {
+            Err(ZeroTileSize(0, 16)) => true,
+            _ => false
+        });
+        assert!(ArrayAtlasBuilder::new(Path::new("."), 16, 0).is_err());
+    }
+
+    #[test]
+    fn color_map_with_wrong_pixel_count_is_an_error() {
+        let pixels = Vec::from_elem(3, [1.0f32, 1.0, 1.0]);
+        assert!(match ColorMapF32::from_pixels(2, 2, pixels) {
+            Err(WrongDimensions(_, (2, 2), (3, 1))) => true,
+            _ => false
+        });
+    }
+
+    #[test]
+    fn dilation_grows_one_ring_per_pass() {
+        let mut tile = ImageBuf::from_pixel(5, 5, Rgba(0u8, 0, 0, 0));
+        tile.put_pixel(2, 2, Rgba(255, 0, 0, 255));
+
+        for pass in range(1u32, 3) {
+            let mut atlas = AtlasBuilder::new(Path::new("."), 5, 5).unwrap();
+            let (x0, y0) = atlas.replace("dot", tile.clone()).unwrap();
+            atlas.dilate_edges(pass);
+            for y in range(0u32, 5) {
+                for x in range(0u32, 5) {
+                    let ring = cmp::max(if x > 2 { x - 2 } else { 2 - x },
+                        if y > 2 { y - 2 } else { 2 - y });
+                    let (r, _, _, a) = atlas.image().get_pixel(x0 + x, y0 + y).channels();
+                    assert_eq!(r, if ring <= pass { 255 } else { 0 });
+                    assert_eq!(a, if ring == 0 { 255 } else { 0 });
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn prebuilt_dilation_stays_within_tiles() {
+        let mut img = ImageBuf::from_pixel(4, 2, Rgba(0u8, 0, 0, 0));
+        img.put_pixel(1, 0, Rgba(255, 0, 0, 255));
+        let mut positions = HashMap::new();
+        positions.insert("dot".to_string(), (0u32, 0u32));
+        positions.insert("empty".to_string(), (2, 0));
+        dilate_tiles(&mut img, &positions, (2, 2), 1);
+        for &(x, y) in [(0u32, 0u32), (0, 1), (1, 1)].iter() {
+            assert_eq!(img.get_pixel(x, y).channels(), (255, 0, 0, 0));
+        }
+        for &(x, y) in [(2u32, 0u32), (2, 1)].iter() {
+            assert_eq!(img.get_pixel(x, y).channels(), (0, 0, 0, 0));
+        }
+    }
+
+    #[test]
+    fn initial_size_rounds_up_to_power_of_two() {
+        let atlas = AtlasBuilder::with_initial_size(Path::new("."), 16, 16, 5).unwrap();
+        assert_eq!(atlas.dimensions(), (128, 128));
+        let atlas = AtlasBuilder::with_initial_size(Path::new("."), 16, 16, 0).unwrap();
+        assert_eq!(atlas.dimensions(), (16, 16));
+    }
+
+    #[test]
+    fn alternating_growth_keeps_existing_tiles() {
+        let mut atlas = AtlasBuilder::builder(Path::new("."), 16, 16)
+            .growth(Alternating)
+            .initial_size(1)
+            .build()
+            .unwrap();
+        let red = atlas.replace("red", ImageBuf::from_pixel(16, 16, Rgba(255u8, 0, 0, 255)));
+        assert_eq!(red, Ok((0, 0)));
+        let green = atlas.replace("green", ImageBuf::from_pixel(16, 16, Rgba(0u8, 255, 0, 255)));
+        assert_eq!(green, Ok((16, 0)));
+
+        assert_eq!(atlas.dimensions(), (32, 16));
+        assert_eq!(atlas.image().get_pixel(15, 15).channels(), (255, 0, 0, 255));
+        let uvs = atlas.uv_rects();
+        assert_eq!(uvs.get(&"red".to_string()), Some(&[0.0, 0.0, 0.5, 1.0]));
+        assert_eq!(uvs.get(&"green".to_string()), Some(&[0.5, 0.0, 1.0, 1.0]));
+    }
+
+    #[test]
+    fn capacity_is_limited_by_max_size() {
+        let mut atlas = AtlasBuilder::builder(Path::new("."), 16, 16)
+            .max_size(64)
+            .capacity(100)
+            .build()
+            .unwrap();
+        assert_eq!(atlas.dimensions(), (64, 64));
+
+        let img = ImageBuf::from_pixel(16, 16, Rgba(255u8, 255, 255, 255));
+        for i in range(0u, 16) {
+            assert!(atlas.replace(format!("tile{}", i).as_slice(), img.clone()).is_ok());
+        }
+        assert!(match atlas.replace("tile16", img) { Err(AtlasFull(..)) => true, _ => false });
+        assert_eq!(atlas.dimensions(), (64, 64));
+    }
+
+    #[test]
+    fn placeholder_only_replaces_missing_or_broken_tiles() {
+        let mut atlas = AtlasBuilder::with_missing_placeholder(Path::new("does-not-exist"), 2, 2)
+            .unwrap();
+        assert!(atlas.load("missing").is_ok());
+        assert!(atlas.load_bytes("garbage", b"not a png").is_ok());
+
+        let mut large = MemWriter::new();
+        image::ImageRgba8(ImageBuf::from_pixel(3, 3, Rgba(255u8, 255, 255, 255)))
+            .save(&mut large, image::PNG).unwrap();
+        let result = atlas.load_bytes("large", large.get_ref());
+        assert!(match result { Err(TileSizeMismatch(..)) => true, _ => false });
+        assert!(!atlas.contains("large"));
+    }
+
+    #[bench]
+    fn load_100_tiles(b: &mut Bencher) {
+        let dir = TempDir::new("glium_voxel").unwrap();
+        let names: Vec<String> = range(0u, 100).map(|i| format!("tile{}", i)).collect();
+        for (i, name) in names.iter().enumerate() {
+            let img = ImageBuf::from_pixel(16, 16, Rgba(i as u8, 0, 0, 255));
+            let mut file = File::create(&dir.path().join(format!("{}.png", name))).unwrap();
+            image::ImageRgba8(img).save(&mut file, image::PNG).unwrap();
+        }
+        b.iter(|| {
+            let mut atlas = AtlasBuilder::new(dir.path().clone(), 16, 16).unwrap();
+            for name in names.iter() {
+                atlas.load(name.as_slice()).unwrap();
+            }
+            atlas
+        });
+    }
+}